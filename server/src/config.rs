@@ -0,0 +1,233 @@
+//! How the server is set up, from `server.toml` and the command line, the
+//! command line winning:
+//!
+//! ```toml
+//! data_dir = "/var/lib/calicomp"
+//! max_sessions = 50
+//! shutdown_grace = 30
+//!
+//! [ssh]
+//! address = "0.0.0.0"
+//! port = 2222
+//! inactivity_timeout = 3600
+//! guests = true
+//!
+//! [web]
+//! enabled = false
+//! ```
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use serde::Deserialize;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Serve the cocktail recipes over ssh and the web")]
+pub struct Args {
+    /// The config file to read, `server.toml` when it exists
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+    /// Where the host key, `authorized_keys` and the collections are
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    #[arg(long)]
+    pub ssh_address: Option<IpAddr>,
+    #[arg(long)]
+    pub ssh_port: Option<u16>,
+    #[arg(long)]
+    pub web_address: Option<IpAddr>,
+    #[arg(long)]
+    pub web_port: Option<u16>,
+    /// The ssh host key, made when it doesn't exist
+    #[arg(long)]
+    pub host_key: Option<PathBuf>,
+    /// Seconds an ssh connection may sit idle before it is closed, 0 for
+    /// ever
+    #[arg(long)]
+    pub inactivity_timeout: Option<u64>,
+    /// Terminal sessions open at once, over ssh and the web together
+    #[arg(long)]
+    pub max_sessions: Option<usize>,
+    /// Seconds sessions get to wrap up when the server is asked to stop
+    #[arg(long)]
+    pub shutdown_grace: Option<u64>,
+    /// Let unknown ssh keys in to look at the house recipes
+    #[arg(long)]
+    pub guests: bool,
+    /// Only serve ssh
+    #[arg(long, conflicts_with = "web_only")]
+    pub ssh_only: bool,
+    /// Only serve the web frontend
+    #[arg(long)]
+    pub web_only: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data_dir: PathBuf,
+    /// Terminal sessions open at once, over ssh and the web together. No
+    /// limit when not set.
+    pub max_sessions: Option<usize>,
+    /// In seconds, how long sessions get to wrap up when the server is
+    /// asked to stop.
+    pub shutdown_grace: u64,
+    pub ssh: SshConfig,
+    pub web: WebConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SshConfig {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+    /// Relative to the data directory.
+    pub host_key: PathBuf,
+    /// Relative to the data directory.
+    pub authorized_keys: PathBuf,
+    /// In seconds, 0 to never close idle connections.
+    pub inactivity_timeout: u64,
+    /// In seconds, how long to wait before telling a client its key was
+    /// rejected.
+    pub auth_rejection_time: u64,
+    /// Whether unknown keys get a read only session instead of none.
+    pub guests: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("."),
+            max_sessions: None,
+            shutdown_grace: 10,
+            ssh: SshConfig::default(),
+            web: WebConfig::default(),
+        }
+    }
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 2222,
+            host_key: PathBuf::from("keypair"),
+            authorized_keys: PathBuf::from("authorized_keys"),
+            inactivity_timeout: 3600,
+            auth_rejection_time: 3,
+            guests: false,
+        }
+    }
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 1111,
+        }
+    }
+}
+
+impl Config {
+    /// The config file default path, read when it exists.
+    const FILE: &'static str = "server.toml";
+
+    /// Read the config file named in `args`, and apply the rest of `args`
+    /// on top.
+    pub fn load(args: Args) -> anyhow::Result<Config> {
+        let path = args.config.clone().unwrap_or_else(|| PathBuf::from(Self::FILE));
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).with_context(|| format!("reading {}", path.display()))?,
+            // Only the default may be missing, a file asked for has to be there.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && args.config.is_none() => Config::default(),
+            Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+        };
+        config.apply(args);
+        if !config.ssh.enabled && !config.web.enabled {
+            bail!("Both ssh and the web are disabled, there is nothing to serve");
+        }
+        Ok(config)
+    }
+
+    fn apply(&mut self, args: Args) {
+        let Args {
+            config: _,
+            data_dir,
+            ssh_address,
+            ssh_port,
+            web_address,
+            web_port,
+            host_key,
+            inactivity_timeout,
+            max_sessions,
+            shutdown_grace,
+            guests,
+            ssh_only,
+            web_only,
+        } = args;
+        set(&mut self.data_dir, data_dir);
+        set(&mut self.ssh.address, ssh_address);
+        set(&mut self.ssh.port, ssh_port);
+        set(&mut self.web.address, web_address);
+        set(&mut self.web.port, web_port);
+        set(&mut self.ssh.host_key, host_key);
+        set(&mut self.ssh.inactivity_timeout, inactivity_timeout);
+        if max_sessions.is_some() {
+            self.max_sessions = max_sessions;
+        }
+        set(&mut self.shutdown_grace, shutdown_grace);
+        self.ssh.guests |= guests;
+        if ssh_only {
+            (self.ssh.enabled, self.web.enabled) = (true, false);
+        }
+        if web_only {
+            (self.ssh.enabled, self.web.enabled) = (false, true);
+        }
+    }
+
+    /// `path` in the data directory, unless it is absolute.
+    pub fn data_path(&self, path: &Path) -> PathBuf {
+        self.data_dir.join(path)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace)
+    }
+}
+
+impl SshConfig {
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    pub fn inactivity_timeout(&self) -> Option<Duration> {
+        (self.inactivity_timeout > 0).then(|| Duration::from_secs(self.inactivity_timeout))
+    }
+}
+
+impl WebConfig {
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+}
+
+fn set<T>(value: &mut T, arg: Option<T>) {
+    if let Some(arg) = arg {
+        *value = arg;
+    }
+}
@@ -0,0 +1,52 @@
+//! Commands run over ssh without a terminal, like `ssh bar.local list`.
+//! They are the subcommands of the command line app, run on the house
+//! collection.
+
+use calicomp::cli::{self, Cli, Command};
+use calicomp::sys::data::Reposotory;
+use clap::Parser;
+
+/// What a command printed, and how it went.
+pub struct Output {
+    pub status: u32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Run `command`, the words of it split like a shell would, if it didn't
+/// have quotes.
+pub fn run(command: &str, repo: &Reposotory) -> Output {
+    let mut output = Output { status: 0, stdout: Vec::new(), stderr: Vec::new() };
+    let args = std::iter::once("calicomp").chain(command.split_whitespace());
+    let command = match Cli::try_parse_from(args) {
+        Ok(Cli { command: Some(command) }) => command,
+        Ok(Cli { command: None }) => return output.failed(2, "No command given, try `help`"),
+        // Help and the version are errors too, printed to stdout.
+        Err(err) => {
+            output.status = err.exit_code() as u32;
+            let rendered = err.render().to_string().into_bytes();
+            if err.use_stderr() {
+                output.stderr = rendered;
+            } else {
+                output.stdout = rendered;
+            }
+            return output;
+        }
+    };
+    // The saved queries are the server's, not of whoever logged in.
+    if let Command::Query { save: Some(_), .. } = command {
+        return output.failed(1, "Queries can't be saved over ssh");
+    }
+    if let Err(err) = cli::run(command, repo, &mut output.stdout) {
+        return output.failed(1, &err.to_string());
+    }
+    output
+}
+
+impl Output {
+    fn failed(mut self, status: u32, message: &str) -> Output {
+        self.status = status;
+        self.stderr = format!("Error: {message}\n").into_bytes();
+        self
+    }
+}
@@ -1,25 +1,48 @@
 use anyhow::{anyhow, Result};
 use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{ConnectInfo, DefaultBodyLimit, WebSocketUpgrade};
-use axum::response::IntoResponse;
+use axum::extract::{ConnectInfo, DefaultBodyLimit, State, WebSocketUpgrade};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
-use calicomp::app::{events::update, App};
-use crossterm::event::Event;
+use calicomp::app::{error::AppError, events::update, log::Log, role::Role, shared::{Collection, SharedRepo}, App};
+use crossterm::{
+    cursor::{MoveTo, Show},
+    event::{DisableMouseCapture, EnableMouseCapture, Event},
+    execute,
+    style::ResetColor,
+    terminal::{Clear, ClearType},
+};
 use futures::stream::SplitStream;
 use futures_util::{SinkExt, StreamExt};
 use ratatui::{prelude::*, Terminal};
-use std::{io::Write, net::SocketAddr};
-use tokio::{sync::mpsc::{self, Receiver}, task::JoinHandle};
+use calicomp::sys::data::Reposotory;
+use std::{io::Write, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{sync::{mpsc, watch, OwnedSemaphorePermit, Semaphore}, task::JoinHandle};
 use tokio::task;
+use tokio::time::Instant;
 use tokio::{net::TcpListener, sync::mpsc::Sender};
 use tower_http::cors::{self, CorsLayer};
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::{info, info_span, Instrument};
-
-use crate::parser::{self, parse_event};
+use tracing::{info, info_span, instrument::WithSubscriber, Dispatch, Instrument};
+
+use crate::parser::parse_events;
+use crate::protocol::{self, ClientMessage, Level, ServerMessage};
+use crate::shutdown::{self, Shutdown};
+
+/// How long a client has to say hello once connected.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What every web session shares.
+#[derive(Clone)]
+struct Shared {
+    repo: SharedRepo,
+    /// Shared with ssh.
+    sessions: Arc<Semaphore>,
+    shutdown: Shutdown,
+}
 
-pub async fn start(addr: &SocketAddr) -> Result<()> {
+pub async fn start(addr: &SocketAddr, repo: SharedRepo, sessions: Arc<Semaphore>, shutdown: Shutdown) -> Result<()> {
     info!(?addr, "starting http server");
 
     let listener = TcpListener::bind(&addr).await?;
@@ -36,6 +59,7 @@ pub async fn start(addr: &SocketAddr) -> Result<()> {
 
         Router::new()
             .route("/", get(handle_connect))
+            .with_state(Shared { repo, sessions, shutdown })
             .layer(cors)
             .layer(trace)
             .layer(limit)
@@ -53,15 +77,20 @@ pub async fn start(addr: &SocketAddr) -> Result<()> {
 async fn handle_connect(
     socket: WebSocketUpgrade,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
+    State(Shared { repo, sessions, shutdown }): State<Shared>,
+) -> Response {
     let span = info_span!("websocket", %addr);
+    let Ok(permit) = sessions.try_acquire_owned() else {
+        info!(parent: &span, "Turned away, too many sessions are open");
+        return (StatusCode::SERVICE_UNAVAILABLE, "Too many sessions are open, try again later").into_response();
+    };
     let socket = socket.write_buffer_size(0);
 
     socket.on_upgrade(move |socket| {
         async move {
             info!("connection opened");
 
-            match handle_connection(socket).await {
+            match handle_connection(socket, repo, permit, shutdown).await {
                 Ok(()) => {
                     info!("connection closed");
                 }
@@ -73,10 +102,11 @@ async fn handle_connect(
         }
         .instrument(span)
     })
+    .into_response()
 }
 
-async fn handle_connection(socket: WebSocket) -> Result<()> {
-    let mut instance = create_term(socket)?;
+async fn handle_connection(socket: WebSocket, repo: SharedRepo, permit: OwnedSemaphorePermit, shutdown: Shutdown) -> Result<()> {
+    let mut instance = create_term(socket, repo, permit, shutdown)?;
     tokio::task::spawn(async move {
         let res = instance.drive().await;
         tracing::info!("Instance stopped: {res:?}");
@@ -85,20 +115,24 @@ async fn handle_connection(socket: WebSocket) -> Result<()> {
     Ok(())
 }
 
-fn create_term(socket: WebSocket) -> Result<Instance> {
+fn create_term(socket: WebSocket, repo: SharedRepo, permit: OwnedSemaphorePermit, shutdown: Shutdown) -> Result<Instance> {
     let (mut stdout, stdin) = socket.split();
 
     let (stdout, task) = {
-        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(8);
+        // Room for a burst of frames and control messages written before
+        // this task gets to run, like everything sent on connecting.
+        let (tx, mut rx) = mpsc::channel::<Message>(64);
 
         let task = task::spawn(
             async move {
                 while let Some(msg) = rx.recv().await {
-                    if stdout.send(Message::Binary(msg)).await.is_err() {
+                    if stdout.send(msg).await.is_err() {
                         info!("couldn't push data into socket, killing stdout");
                         return;
                     }
                 }
+                // Nothing more to write, so hang up properly.
+                let _ = stdout.send(Message::Close(None)).await;
             }
             .in_current_span(),
         );
@@ -112,11 +146,19 @@ fn create_term(socket: WebSocket) -> Result<Instance> {
     let backend = CrosstermBackend::new(writer);
     let term = Terminal::new(backend)?;
 
+    let log = Log::default();
+    let dispatch = crate::session_dispatch(&log);
     let instance= Instance {
         pty: term,
         stdin,
         task,
-        app: calicomp::app::App::new(),
+        updates: repo.subscribe(),
+        // Nobody logs in over the web yet, so anyone who connects only looks.
+        app: tracing::dispatcher::with_default(&dispatch, || App::with_collections("web", vec![Collection::house(repo, Role::Guest)], log)),
+        dispatch,
+        _permit: permit,
+        shutdown,
+        title: String::new(),
     };
 
     Ok(instance)
@@ -129,7 +171,15 @@ struct Instance {
     app: App,
     pty: ProxyTerminal,
     stdin: SplitStream<WebSocket>,
-    task: JoinHandle<()>
+    task: JoinHandle<()>,
+    dispatch: Dispatch,
+    /// Wakes the session up to redraw when another one changes the repository.
+    updates: watch::Receiver<Arc<Reposotory>>,
+    /// Counts the session towards the most that may be open at once.
+    _permit: OwnedSemaphorePermit,
+    shutdown: Shutdown,
+    /// Last sent to the client.
+    title: String,
 }
 
 impl Drop for Instance {
@@ -140,81 +190,217 @@ impl Drop for Instance {
 
 impl Instance {
     pub async fn draw_state(&mut self) -> anyhow::Result<()> {
-        self.pty.draw(|f| calicomp::ui::entry(f, &mut self.app))?;
+        let Instance { pty, app, dispatch, .. } = self;
+        tracing::dispatcher::with_default(dispatch, || pty.draw(|f| calicomp::ui::entry(f, app)))?;
+        let title = format!("Calicomp: {}", self.app.current_collection().name);
+        if title != self.title {
+            self.send(&ServerMessage::Title { title: title.clone() })?;
+            self.title = title;
+        }
         Ok(())
     }
 
+    fn send(&mut self, message: &ServerMessage) -> std::io::Result<()> {
+        self.pty.backend_mut().writer_mut().send(message)
+    }
+
+    fn notify(&mut self, level: Level, text: impl Into<String>) -> std::io::Result<()> {
+        self.send(&ServerMessage::Notice { level, text: text.into() })
+    }
+
     pub async fn drive(&mut self) -> anyhow::Result<()> {
+        if !self.handshake().await? {
+            self.finish_output().await;
+            return Ok(());
+        }
         self.pty.clear()?;
+        execute!(self.pty.backend_mut(), EnableMouseCapture)?;
         self.draw_state().await?;
-        loop {
-            if let Some(event) = self.next_event().await? {
-                if self.step(event).await? {
-                    break;
+        let shutdown = self.shutdown.clone();
+        // When the server goes down, once it began to.
+        let mut deadline: Option<Instant> = None;
+        'session: loop {
+            let tick = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline.min(Instant::now() + Duration::from_secs(1))).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                msg = self.stdin.next() => {
+                    for event in self.events(msg).await? {
+                        if self.step(event).await {
+                            break 'session;
+                        }
+                    }
+                }
+                Ok(()) = self.updates.changed() => {
+                    if self.app.sync() {
+                        self.draw_state().await?;
+                    }
+                }
+                // Toasts only go away when drawn.
+                () = crate::toasts_expire(&self.app.log) => {
+                    self.draw_state().await?;
                 }
-            } 
+                begun = shutdown.begun(), if deadline.is_none() => {
+                    deadline = Some(begun);
+                    let banner = shutdown::banner(begun);
+                    self.notify(Level::Warning, banner.clone())?;
+                    self.app.banner = Some(banner);
+                    self.draw_state().await?;
+                }
+                () = tick => {
+                    let deadline = deadline.expect("only ticks once begun");
+                    if Instant::now() >= deadline {
+                        let backend = self.pty.backend_mut();
+                        execute!(backend, DisableMouseCapture, ResetColor, Clear(ClearType::All), MoveTo(0, 0), Show)?;
+                        backend.write_all(b"The server went down\r\n")?;
+                        Write::flush(backend)?;
+                        self.notify(Level::Warning, "The server went down")?;
+                        self.finish_output().await;
+                        return Ok(());
+                    }
+                    self.app.banner = Some(shutdown::banner(deadline));
+                    self.draw_state().await?;
+                }
+            }
         }
 
+        execute!(self.pty.backend_mut(), DisableMouseCapture)?;
+        self.finish_output().await;
         Ok(())
     }
 
-    pub async fn next_event(&mut self) -> anyhow::Result<Option<Event>> {
-        let msg = self.stdin.next().await.ok_or_else(||anyhow!("stdin closed"))?;
+    /// Let the socket send what was written so far, and close it.
+    async fn finish_output(&mut self) {
+        // The task stops once the last sender is gone.
+        let (closed, _) = mpsc::channel(1);
+        drop(std::mem::replace(&mut self.pty.backend_mut().writer_mut().stdout, closed));
+        if tokio::time::timeout(Duration::from_secs(1), &mut self.task).await.is_err() {
+            info!("Gave up sending the rest to the socket");
+        }
+    }
+
+    /// Agree on a protocol version with the client, which has to say hello
+    /// first. Returns whether they did, having told them why not otherwise.
+    async fn handshake(&mut self) -> anyhow::Result<bool> {
+        let msg = tokio::time::timeout(HELLO_TIMEOUT, self.stdin.next())
+            .await
+            .map_err(|_| anyhow!("the client never said hello"))?;
+        let Some(ClientMessage::Hello { versions }) = self.message(msg)? else {
+            self.notify(Level::Error, "Say hello first, with the protocol versions you speak")?;
+            return Ok(false);
+        };
+        match protocol::negotiate(&versions) {
+            Some(version) => {
+                tracing::debug!(version, "agreed on a protocol version");
+                self.send(&ServerMessage::Hello { version })?;
+                Ok(true)
+            }
+            None => {
+                let text = format!("This server speaks up to protocol version {}, none of {versions:?}", protocol::VERSION);
+                self.notify(Level::Error, text)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Read a message from the client. Ones that don't make sense are
+    /// reported back to the client and skipped.
+    fn message(&mut self, msg: Option<Result<Message, axum::Error>>) -> anyhow::Result<Option<ClientMessage>> {
+        let msg = msg.ok_or_else(||anyhow!("stdin closed"))?;
 
-        let bytes = match msg {
+        match msg {
             Ok(Message::Text(msg)) => {
-                msg.into_bytes()
+                match serde_json::from_str(&msg) {
+                    Ok(message) => Ok(Some(message)),
+                    Err(err) => {
+                        self.notify(Level::Error, format!("Can't read the message: {err}"))?;
+                        Ok(None)
+                    }
+                }
+            }
+
+            Ok(Message::Binary(_)) => {
+                self.notify(Level::Error, "Only the server sends binary messages")?;
+                Ok(None)
             }
 
-            Ok(Message::Binary(msg)) => {
-                msg
+            Ok(Message::Close(_)) => {
+                Err(anyhow!("stdin closed"))
             }
 
-            Ok(_) => {
-                return Err(anyhow!("Can't handle this"));
+            // Answered by axum already.
+            Ok(Message::Ping(_) | Message::Pong(_)) => {
+                Ok(None)
             }
 
             Err(err) => {
-                return Err(err.into())
+                Err(err.into())
             }
+        }
+    }
+
+    pub async fn events(&mut self, msg: Option<Result<Message, axum::Error>>) -> anyhow::Result<Vec<Event>> {
+        let Some(message) = self.message(msg)? else {
+            return Ok(Vec::new());
         };
 
-        if let Some(size) = bytes.strip_prefix(&[0x04]) {
-            let cols = size.first().copied().unwrap_or(0) as u16;
-            let rows = size.last().copied().unwrap_or(0) as u16;
-            tracing::debug!("resized to {rows} x {cols}");
+        match message {
+            ClientMessage::Input { data } => {
+                Ok(parse_events(data.as_bytes()))
+            }
 
-            self.pty.resize(Rect { x: 0, y: 0, width: cols, height: rows })?;
-            self.draw_state().await?;
-            Ok(None)
-        } else {
-            Ok(parse_event(&bytes, false)?)
-        }
+            ClientMessage::Resize { cols, rows } => {
+                tracing::debug!("resized to {rows} x {cols}");
 
+                self.pty.resize(crate::terminal_area(cols.into(), rows.into()))?;
+                self.draw_state().await?;
+                Ok(Vec::new())
+            }
 
-    }
+            ClientMessage::Ping { id } => {
+                self.send(&ServerMessage::Pong { id })?;
+                Ok(Vec::new())
+            }
+
+            ClientMessage::Clipboard { text } => {
+                Ok(vec![Event::Paste(text)])
+            }
 
-    pub async fn step(&mut self, event: Event) -> anyhow::Result<bool> {
-        update(&mut self.app, event).await.unwrap();
-        self.pty.draw(|frame| calicomp::ui::entry(frame, &mut self.app))?;
-        if self.app.should_quit {
-            Ok(true)
-        } else {
-            Ok(false)
+            ClientMessage::Hello { .. } => {
+                self.notify(Level::Error, "Already agreed on a protocol version")?;
+                Ok(Vec::new())
+            }
         }
     }
-}
 
-
-pub struct EventHandler {
-    channel: Receiver<Event>,
+    /// Handle `event`, returning whether the session is over. Nothing that
+    /// goes wrong here ends it, it is reported to the user instead.
+    pub async fn step(&mut self, event: Event) -> bool {
+        let result = update(&mut self.app, event).with_subscriber(self.dispatch.clone()).await;
+        tracing::dispatcher::with_default(&self.dispatch, || {
+            if let Err(err) = result {
+                self.app.report(&err);
+            }
+            // There is no local terminal to run an editor in.
+            if std::mem::take(&mut self.app.open_in_editor) {
+                self.app.report(&AppError::Unsupported("Editing in $EDITOR"));
+            }
+        });
+        if let Err(err) = self.draw_state().await {
+            tracing::dispatcher::with_default(&self.dispatch, || self.app.report(&AppError::Draw(err.to_string())));
+        }
+        self.app.should_quit
+    }
 }
 
 
 #[derive(Debug)]
 struct ProxyWriter {
     buffer: Vec<u8>,
-    stdout: Sender<Vec<u8>>,
+    stdout: Sender<Message>,
 }
 
 impl Write for ProxyWriter {
@@ -229,7 +415,19 @@ impl Write for ProxyWriter {
         // or channel closed it will be an error, but that seems correct.
         // We will just need to be able to recover from such errors.
         self.stdout
-            .try_send(msg)
+            .try_send(Message::Binary(msg))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e))
+    }
+}
+
+impl ProxyWriter {
+    /// Send `message` after whatever was written so far.
+    fn send(&mut self, message: &ServerMessage) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.flush()?;
+        }
+        self.stdout
+            .try_send(Message::Text(serde_json::to_string(message)?))
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e))
     }
 }
@@ -1,21 +1,215 @@
 #![allow(clippy::needless_return)]
-use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::ssh::AppServer;
+use anyhow::Context;
+use calicomp::{app::{log::Log, shared::{Edit, SharedRepo}}, sys::{data::Reposotory, db}};
+use clap::Parser;
+use ratatui::layout::Rect;
+use tokio::sync::Semaphore;
+use tracing::Dispatch;
+use tracing_subscriber::{filter::LevelFilter, fmt, layer::SubscriberExt, Layer};
 
+use crate::{config::{Args, Config}, shutdown::Shutdown, ssh::AppServer, users::Users};
+
+pub mod config;
+pub mod exec;
 pub mod parser;
+pub mod protocol;
+pub mod sftp;
+pub mod shutdown;
 pub mod http;
 pub mod ssh;
+pub mod users;
+
+/// Where a session traces to: the server's own output, and the session's
+/// log pane so its user sees what went wrong.
+pub fn session_dispatch(log: &Log) -> Dispatch {
+    let subscriber = tracing_subscriber::registry()
+        .with(fmt::layer().with_filter(LevelFilter::INFO))
+        .with(log.layer());
+    Dispatch::new(subscriber)
+}
+
+/// Returns when the next toast in `log` goes away, never if none is showing.
+/// Toasts are only dropped when drawn, so redraw then.
+pub async fn toasts_expire(log: &Log) {
+    match log.next_expiry() {
+        Some(expiry) => tokio::time::sleep_until(expiry.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The repository saved at `path`, `None` when nothing was saved there yet.
+pub fn load_repo(path: &Path) -> anyhow::Result<Option<Reposotory>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text).map(Some).with_context(|| format!("reading {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+/// Who changed what in a collection, as saved next to it.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Journal {
+    #[serde(default)]
+    edit: Vec<Edit>,
+}
+
+/// Where the journal of the collection saved at `path` is kept, like
+/// `house.journal.toml` next to `house.toml`.
+fn journal_path(path: &Path) -> PathBuf {
+    path.with_extension("journal.toml")
+}
+
+/// The journal of the collection saved at `path`. One that can't be read
+/// is started over, the collection matters more than its history.
+pub fn load_journal(path: &Path) -> Vec<Edit> {
+    let path = journal_path(path);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            tracing::warn!("Could not read {}, starting it over: {err}", path.display());
+            return Vec::new();
+        }
+    };
+    match toml::from_str::<Journal>(&text) {
+        Ok(journal) => journal.edit,
+        Err(err) => {
+            tracing::warn!("Could not read {}, starting it over: {err}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+/// Save `repo` at `path` and its journal next to it, each all at once so a
+/// crash can't leave half of it.
+pub fn save_repo(repo: &SharedRepo, path: &Path) -> anyhow::Result<()> {
+    // Saves in the background and the one on shutdown share the files
+    // written first.
+    static SAVING: Mutex<()> = Mutex::new(());
+    let _saving = SAVING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    replace_file(path, &toml::to_string(&*repo.snapshot())?)?;
+    replace_file(&journal_path(path), &toml::to_string(&Journal { edit: repo.journal() })?)?;
+    Ok(())
+}
+
+/// Write `text` to `path` through a file next to it, so readers see the
+/// old contents or the new, never half of them.
+fn replace_file(path: &Path, text: &str) -> anyhow::Result<()> {
+    let written = path.with_extension("toml.new");
+    std::fs::write(&written, text).with_context(|| format!("writing {}", written.display()))?;
+    std::fs::rename(&written, path).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+/// How long after a change a collection is saved, so a burst of changes is
+/// written once.
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Save `repo` at `path` after every change to it, so little is lost when
+/// the server doesn't get to go down cleanly. Runs as long as the server.
+pub fn keep_saved(repo: &SharedRepo, path: PathBuf) {
+    let mut changes = repo.subscribe();
+    let repo = repo.clone();
+    tokio::spawn(async move {
+        while changes.changed().await.is_ok() {
+            tokio::time::sleep(SAVE_DELAY).await;
+            changes.mark_unchanged();
+            if let Err(err) = save_repo(&repo, &path) {
+                tracing::warn!("Could not save {}: {err:#}", path.display());
+            }
+        }
+    });
+}
+
+/// The largest terminal a session draws on. Screen buffers are as big as
+/// the terminal, so a client must not get to pick any size it likes.
+const MAX_AREA: (u16, u16) = (1000, 500);
+
+/// The area of a client's terminal `cols` wide and `rows` high, as much of
+/// it as a session draws on. Used by every transport.
+pub fn terminal_area(cols: u32, rows: u32) -> Rect {
+    let clamp = |cells: u32, max: u16| u16::try_from(cells).unwrap_or(u16::MAX).min(max);
+    Rect::new(0, 0, clamp(cols, MAX_AREA.0), clamp(rows, MAX_AREA.1))
+}
+
+/// Sessions open at once when there is no limit, as many as can be waited
+/// for at once.
+const UNLIMITED: usize = u32::MAX as usize;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
-    tokio::spawn(async {
-        let addr: SocketAddr = "127.0.0.1:1111".parse().unwrap();
-        http::start(&addr).await.unwrap();
+    let config = Config::load(Args::parse())?;
+    let house = config.data_path(Path::new("house.toml"));
+    let saved = load_repo(&house)?.unwrap_or_else(|| {
+        tracing::info!("No {} found, starting from the demo recipes", house.display());
+        db::demo()
     });
+    // Every session, over ssh or the web, works on this one.
+    let repo = SharedRepo::with_journal(saved, load_journal(&house));
+    keep_saved(&repo, house.clone());
+    let users = config
+        .ssh
+        .enabled
+        .then(|| {
+            let authorized_keys = config.data_path(&config.ssh.authorized_keys);
+            Users::load(&authorized_keys, repo.clone(), config.ssh.guests, config.data_path(Path::new("users")))
+        })
+        .transpose()?;
+    let max_sessions = config.max_sessions.unwrap_or(UNLIMITED).min(UNLIMITED);
+    let sessions = Arc::new(Semaphore::new(max_sessions));
+    let shutdown = Shutdown::new();
+
+    let web = async {
+        if config.web.enabled {
+            http::start(&config.web.addr(), repo.clone(), sessions.clone(), shutdown.clone()).await?;
+        }
+        anyhow::Ok(())
+    };
+
+    let ssh = async {
+        if let Some(users) = users.clone() {
+            let mut server = AppServer::new(users, sessions.clone(), shutdown.clone());
+            server.run(&config.ssh, &config.data_path(&config.ssh.host_key)).await?;
+        }
+        anyhow::Ok(())
+    };
+
+    // Dropping the servers stops them from taking new connections, the
+    // ones open go on until they see the shutdown.
+    tokio::select! {
+        served = async { tokio::try_join!(web, ssh) } => {
+            served?;
+            return Ok(());
+        }
+        () = shutdown::signal() => {}
+    }
+
+    let grace = config.shutdown_grace();
+    tracing::info!("Going down in {} seconds", grace.as_secs());
+    shutdown.begin(grace);
+    // Every session gives its permit back when it is closed.
+    let closed = sessions.acquire_many(max_sessions as u32);
+    tokio::select! {
+        closed = tokio::time::timeout(grace + Duration::from_secs(2), closed) => {
+            if closed.is_err() {
+                tracing::warn!("Some sessions didn't close in time");
+            }
+        }
+        () = shutdown::signal() => tracing::warn!("Asked again, going down right away"),
+    }
 
-    let mut server = AppServer::new();
-    server.run().await.expect("Failed running server");
-    tracing::info!("Started server");
+    save_repo(&repo, &house)?;
+    if let Some(users) = &users {
+        users.save()?;
+    }
+    tracing::info!("Saved the collections, bye");
+    Ok(())
 }
@@ -114,6 +114,48 @@ pub fn parse_event(
     }
 }
 
+/// Parse every event in `buffer`.
+///
+/// A single read can hold many events, like keys typed ahead, an unbracketed
+/// paste or a burst of mouse reports while dragging. As crossterm does, bytes
+/// are added one at a time until they make an event, then the next one
+/// starts. An unfinished sequence at the end is dropped, and so are those
+/// that can't be parsed, rather than ending the session over them.
+pub fn parse_events(buffer: &[u8]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut start = 0;
+    for end in 1..=buffer.len() {
+        let pending = &buffer[start..end];
+        match parse_event(pending, end < buffer.len()) {
+            Ok(Some(event)) => {
+                events.push(event);
+                start = end;
+            }
+            Ok(None) if finished_csi(pending) => {
+                tracing::debug!("Skipped the sequence {pending:?}");
+                start = end;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                tracing::warn!("Skipped the sequence {pending:?}: {err}");
+                start = end;
+            }
+        }
+    }
+    events
+}
+
+/// Whether `pending` is a whole CSI sequence that makes no event, like the
+/// answer to a cursor position query, rather than the start of one.
+fn finished_csi(pending: &[u8]) -> bool {
+    match pending {
+        // Mouse reports and linux console keys go on after their final byte.
+        [b'\x1B', b'[', b'M' | b'['] => false,
+        [b'\x1B', b'[', .., last] => (64..=126).contains(last),
+        _ => false,
+    }
+}
+
 // converts KeyCode to KeyEvent (adds shift modifier in case of uppercase characters)
 fn char_code_to_event(code: KeyCode) -> KeyEvent {
     let modifiers = match code {
@@ -235,11 +277,12 @@ pub(crate) fn parse_csi_cursor_position(buffer: &[u8]) -> io::Result<Option<Even
 
     let mut split = s.split(';');
 
-    let _y = next_parsed::<u16>(&mut split)? - 1;
-    let _x = next_parsed::<u16>(&mut split)? - 1;
+    let y = next_parsed::<u16>(&mut split)?.saturating_sub(1);
+    let x = next_parsed::<u16>(&mut split)?.saturating_sub(1);
 
-    //Ok(Some(InternalEvent::CursorPosition(x, y)))
-    todo!()
+    // Nothing here asks for the cursor position.
+    tracing::debug!(x, y, "Ignored a cursor position report");
+    Ok(None)
 }
 
 fn parse_csi_keyboard_enhancement_flags(buffer: &[u8]) -> io::Result<Option<Event>> {
@@ -271,8 +314,9 @@ fn parse_csi_keyboard_enhancement_flags(buffer: &[u8]) -> io::Result<Option<Even
     //     flags |= KeyboardEnhancementFlags::REPORT_ASSOCIATED_TEXT;
     // }
 
-    //Ok(Some(InternalEvent::KeyboardEnhancementFlags(flags)))
-    todo!()
+    // Nothing here asks for the flags.
+    tracing::debug!(?flags, "Ignored the keyboard enhancement flags");
+    Ok(None)
 }
 
 fn parse_csi_primary_device_attributes(buffer: &[u8]) -> io::Result<Option<Event>> {
@@ -284,8 +328,8 @@ fn parse_csi_primary_device_attributes(buffer: &[u8]) -> io::Result<Option<Event
     // exposed in the crossterm API so we don't need to parse the individual attributes yet.
     // See <https://vt100.net/docs/vt510-rm/DA1.html>
 
-    //Ok(Some(InternalEvent::PrimaryDeviceAttributes))
-    todo!()
+    tracing::debug!("Ignored the primary device attributes");
+    Ok(None)
 }
 
 fn parse_modifiers(mask: u8) -> KeyModifiers {
@@ -664,8 +708,8 @@ pub(crate) fn parse_csi_rxvt_mouse(buffer: &[u8]) -> io::Result<Option<Event>> {
         .ok_or_else(could_not_parse_event_error)?;
     let (kind, modifiers) = parse_cb(cb)?;
 
-    let cx = next_parsed::<u16>(&mut split)? - 1;
-    let cy = next_parsed::<u16>(&mut split)? - 1;
+    let cx = next_parsed::<u16>(&mut split)?.saturating_sub(1);
+    let cy = next_parsed::<u16>(&mut split)?.saturating_sub(1);
 
     Ok(Some(Event::Mouse(MouseEvent {
         kind,
@@ -692,8 +736,8 @@ pub(crate) fn parse_csi_normal_mouse(buffer: &[u8]) -> io::Result<Option<Event>>
     // See http://www.xfree86.org/current/ctlseqs.html#Mouse%20Tracking
     // The upper left character position on the terminal is denoted as 1,1.
     // Subtract 1 to keep it synced with cursor
-    let cx = u16::from(buffer[4].saturating_sub(32)) - 1;
-    let cy = u16::from(buffer[5].saturating_sub(32)) - 1;
+    let cx = u16::from(buffer[4].saturating_sub(32)).saturating_sub(1);
+    let cy = u16::from(buffer[5].saturating_sub(32)).saturating_sub(1);
 
     Ok(Some(Event::Mouse(MouseEvent {
         kind,
@@ -722,8 +766,8 @@ pub(crate) fn parse_csi_sgr_mouse(buffer: &[u8]) -> io::Result<Option<Event>> {
     // See http://www.xfree86.org/current/ctlseqs.html#Mouse%20Tracking
     // The upper left character position on the terminal is denoted as 1,1.
     // Subtract 1 to keep it synced with cursor
-    let cx = next_parsed::<u16>(&mut split)? - 1;
-    let cy = next_parsed::<u16>(&mut split)? - 1;
+    let cx = next_parsed::<u16>(&mut split)?.saturating_sub(1);
+    let cy = next_parsed::<u16>(&mut split)?.saturating_sub(1);
 
     // When button 3 in Cb is used to represent mouse release, you can't tell which button was
     // released. SGR mode solves this by having the sequence end with a lowercase m if it's a
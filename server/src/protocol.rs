@@ -0,0 +1,129 @@
+//! What the web frontend and the server say to each other over the
+//! websocket. Binary messages from the server are terminal output, to be
+//! written as is. Everything else is a JSON text message, tagged by its
+//! `type`:
+//!
+//! ```json
+//! {"type": "hello", "versions": [1]}
+//! {"type": "input", "data": "q"}
+//! {"type": "resize", "cols": 300, "rows": 80}
+//! ```
+//!
+//! The client says hello first, with the versions it speaks, and the server
+//! answers with the one they go on with, or a notice and a close when there
+//! is none.
+
+use serde::{Deserialize, Serialize};
+
+/// The newest version this server speaks.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello { versions: Vec<u32> },
+    /// Typed into the terminal, as the terminal encodes it.
+    Input { data: String },
+    Resize { cols: u16, rows: u16 },
+    /// Answered with a pong with the same `id`.
+    Ping { id: u64 },
+    /// Pasted from the browser's clipboard.
+    Clipboard { text: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Hello { version: u32 },
+    Pong { id: u64 },
+    /// For the browser tab.
+    Title { title: String },
+    /// Something for the user to know that isn't part of the app, like the
+    /// server going down.
+    Notice { level: Level, text: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+/// The version to go on with, the newest both sides speak.
+pub fn negotiate(versions: &[u32]) -> Option<u32> {
+    versions.iter().copied().filter(|&version| (1..=VERSION).contains(&version)).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    use super::*;
+
+    /// `message` after going to JSON and back, and the JSON it went as.
+    fn round_trip<T: Serialize + DeserializeOwned>(message: &T) -> (T, serde_json::Value) {
+        let text = serde_json::to_string(message).unwrap();
+        (serde_json::from_str(&text).unwrap(), serde_json::from_str(&text).unwrap())
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        let messages = [
+            (ClientMessage::Hello { versions: vec![1, 2] }, json!({"type": "hello", "versions": [1, 2]})),
+            (ClientMessage::Input { data: "q\x1b[A".to_string() }, json!({"type": "input", "data": "q\x1b[A"})),
+            (ClientMessage::Resize { cols: 300, rows: 80 }, json!({"type": "resize", "cols": 300, "rows": 80})),
+            (ClientMessage::Ping { id: 7 }, json!({"type": "ping", "id": 7})),
+            (ClientMessage::Clipboard { text: "Gin\nTonic".to_string() }, json!({"type": "clipboard", "text": "Gin\nTonic"})),
+        ];
+        for (message, expected) in messages {
+            let (back, sent) = round_trip(&message);
+            assert_eq!(back, message);
+            assert_eq!(sent, expected);
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let messages = [
+            (ServerMessage::Hello { version: 1 }, json!({"type": "hello", "version": 1})),
+            (ServerMessage::Pong { id: 7 }, json!({"type": "pong", "id": 7})),
+            (ServerMessage::Title { title: "Calicomp: house".to_string() }, json!({"type": "title", "title": "Calicomp: house"})),
+            (
+                ServerMessage::Notice { level: Level::Warning, text: "Going down".to_string() },
+                json!({"type": "notice", "level": "warning", "text": "Going down"}),
+            ),
+            (
+                ServerMessage::Notice { level: Level::Error, text: "Bad message".to_string() },
+                json!({"type": "notice", "level": "error", "text": "Bad message"}),
+            ),
+        ];
+        for (message, expected) in messages {
+            let (back, sent) = round_trip(&message);
+            assert_eq!(back, message);
+            assert_eq!(sent, expected);
+        }
+    }
+
+    #[test]
+    fn unknown_messages_are_rejected() {
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "shout", "text": "hi"}"#).is_err());
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "resize", "cols": 70000, "rows": 1}"#).is_err());
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"data": "q"}"#).is_err());
+    }
+
+    #[test]
+    fn negotiates_the_newest_common_version() {
+        assert_eq!(negotiate(&[VERSION]), Some(VERSION));
+        assert_eq!(negotiate(&[VERSION, VERSION + 1, VERSION + 5]), Some(VERSION));
+        assert_eq!(negotiate(&[VERSION + 1, 1]), Some(1));
+    }
+
+    #[test]
+    fn negotiates_nothing_without_a_common_version() {
+        assert_eq!(negotiate(&[]), None);
+        assert_eq!(negotiate(&[0]), None);
+        assert_eq!(negotiate(&[VERSION + 1, u32::MAX]), None);
+    }
+}
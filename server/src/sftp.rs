@@ -0,0 +1,479 @@
+//! The collections of a login as files over sftp, so they can be edited with
+//! local tools, or mounted with sshfs. Each collection is a folder with one
+//! TOML file per recipe and per product:
+//!
+//! ```text
+//! /house/recipes/Daiquiri.toml
+//! /house/products/Rum.toml
+//! /alice/recipes/...
+//! ```
+//!
+//! Uploads are checked when the file is closed, and rejected with the
+//! reason when they aren't a valid recipe or product.
+
+use std::collections::HashMap;
+
+use calicomp::app::{
+    error::AppError,
+    history::{Change, Step},
+    role::Role,
+    shared::Collection,
+};
+use calicomp::sys::data::{DumbRecipe, Reposotory};
+use calicomp::sys::recipe::{Product, Recipe};
+use russh_sftp::protocol::{
+    Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode, Version,
+};
+use tokio::sync::OwnedSemaphorePermit;
+
+/// The biggest file a client may write, far more than any recipe or product
+/// takes. Uploads are held in memory until they are closed.
+const MAX_FILE: usize = 4 * 1024 * 1024;
+
+/// The most files and folders a session may have open at once, so what it
+/// holds in memory stays below this many times [`MAX_FILE`].
+const MAX_HANDLES: usize = 16;
+
+/// The two folders of a collection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Recipes,
+    Products,
+}
+
+impl Kind {
+    const ALL: [Kind; 2] = [Kind::Recipes, Kind::Products];
+
+    fn folder(&self) -> &'static str {
+        match self {
+            Kind::Recipes => "recipes",
+            Kind::Products => "products",
+        }
+    }
+
+    /// Whether `role` may change files of this kind at all.
+    fn writable(&self, role: Role) -> bool {
+        match self {
+            Kind::Recipes => role >= Role::Bartender,
+            Kind::Products => role == Role::Manager,
+        }
+    }
+}
+
+/// What a path points at, collections by their index.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Root,
+    Collection(usize),
+    Folder(usize, Kind),
+    File(usize, Kind, String),
+}
+
+/// A file opened by the client, and what it has written so far.
+struct OpenFile {
+    collection: usize,
+    kind: Kind,
+    name: String,
+    data: Vec<u8>,
+    /// Whether `data` has to be checked and saved on close.
+    changed: bool,
+}
+
+enum Opened {
+    File(OpenFile),
+    /// The entries of a folder, until they were read.
+    Folder(Option<Vec<File>>),
+}
+
+pub struct Sftp {
+    /// Who changes are recorded under.
+    author: String,
+    collections: Vec<Collection>,
+    opened: HashMap<String, Opened>,
+    next_handle: u64,
+    /// Counts the session towards the most that may be open at once, until
+    /// the client is gone.
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Sftp {
+    pub fn new(author: impl Into<String>, collections: Vec<Collection>, permit: OwnedSemaphorePermit) -> Self {
+        Self {
+            author: author.into(),
+            collections,
+            opened: HashMap::new(),
+            next_handle: 0,
+            _permit: permit,
+        }
+    }
+
+    fn node(&self, path: &str) -> Result<Node, StatusCode> {
+        let parts = normalize(path);
+        let collection = |name: &str| {
+            self.collections
+                .iter()
+                .position(|collection| collection.name == name)
+                .ok_or(StatusCode::NoSuchFile)
+        };
+        let kind = |folder: &str| {
+            Kind::ALL
+                .into_iter()
+                .find(|kind| kind.folder() == folder)
+                .ok_or(StatusCode::NoSuchFile)
+        };
+        match parts.as_slice() {
+            [] => Ok(Node::Root),
+            [name] => Ok(Node::Collection(collection(name)?)),
+            [name, folder] => Ok(Node::Folder(collection(name)?, kind(folder)?)),
+            [name, folder, file] => {
+                let stem = file.strip_suffix(".toml").ok_or(StatusCode::NoSuchFile)?;
+                Ok(Node::File(collection(name)?, kind(folder)?, stem.to_string()))
+            }
+            _ => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    /// What is in the file `name`, `None` when there is no such file.
+    fn read_file(&self, collection: usize, kind: Kind, name: &str) -> Option<Vec<u8>> {
+        let repo = self.collections[collection].repo.snapshot();
+        let text = match kind {
+            Kind::Recipes => toml::to_string(&repo.recipes.get(name)?.clone().dumb()),
+            Kind::Products => toml::to_string(repo.ingredients.get(name)?),
+        };
+        match text {
+            Ok(text) => Some(text.into_bytes()),
+            Err(err) => {
+                tracing::warn!("Could not write {name} as TOML: {err}");
+                None
+            }
+        }
+    }
+
+    fn attributes(&self, node: &Node) -> Result<FileAttributes, StatusCode> {
+        match node {
+            Node::Root | Node::Collection(_) | Node::Folder(..) => Ok(folder()),
+            Node::File(collection, kind, name) => {
+                let data = self.read_file(*collection, *kind, name).ok_or(StatusCode::NoSuchFile)?;
+                Ok(file(data.len(), kind.writable(self.collections[*collection].role)))
+            }
+        }
+    }
+
+    fn list(&self, node: &Node) -> Result<Vec<File>, StatusCode> {
+        let folders = |names: Vec<&str>| names.into_iter().map(|name| File::new(name, folder())).collect();
+        match node {
+            Node::Root => Ok(folders(self.collections.iter().map(|collection| collection.name.as_str()).collect())),
+            Node::Collection(_) => Ok(folders(Kind::ALL.iter().map(Kind::folder).collect())),
+            Node::Folder(collection, kind) => {
+                let repo = self.collections[*collection].repo.snapshot();
+                let names: Vec<&String> = match kind {
+                    Kind::Recipes => repo.recipes.keys().collect(),
+                    Kind::Products => repo.ingredients.keys().collect(),
+                };
+                let files = names
+                    .into_iter()
+                    .filter_map(|name| {
+                        let node = Node::File(*collection, *kind, name.clone());
+                        Some(File::new(format!("{name}.toml"), self.attributes(&node).ok()?))
+                    })
+                    .collect();
+                Ok(files)
+            }
+            Node::File(..) => Err(StatusCode::Failure),
+        }
+    }
+
+    fn open_handle(&mut self, opened: Opened) -> Result<String, StatusCode> {
+        if self.opened.len() >= MAX_HANDLES {
+            tracing::info!(author = self.author, "Refused to open more than {MAX_HANDLES} files at once");
+            return Err(StatusCode::Failure);
+        }
+        self.next_handle += 1;
+        let handle = self.next_handle.to_string();
+        self.opened.insert(handle.clone(), opened);
+        Ok(handle)
+    }
+
+    /// Make `step` on `collection`, like saving an uploaded file.
+    fn apply(&self, collection: usize, step: Step) -> Result<(), AppError> {
+        let collection = &self.collections[collection];
+        collection.apply(&self.author, &step)?;
+        tracing::info!(author = self.author, collection = collection.name, "{}", step.description);
+        Ok(())
+    }
+
+    /// Check and save what was written to `file`.
+    fn save(&self, file: &OpenFile) -> Result<(), String> {
+        let text = std::str::from_utf8(&file.data).map_err(|_| "The file isn't UTF-8 text".to_string())?;
+        // The name of the file is the name of what is in it.
+        let mut table: toml::Table = text.parse().map_err(|err| format!("Invalid TOML: {err}"))?;
+        table.insert("name".to_string(), file.name.clone().into());
+        let repo = self.collections[file.collection].repo.snapshot();
+        let (change, verb) = match file.kind {
+            Kind::Recipes => {
+                let recipe = parse_recipe(table, &repo)?;
+                (Change::recipe(&repo, &file.name, Some(recipe)), "Upload recipe")
+            }
+            Kind::Products => {
+                let product: Product = table.try_into().map_err(|err| format!("Invalid product: {err}"))?;
+                (Change::product(&repo, &file.name, Some(product)), "Upload product")
+            }
+        };
+        let step = Step { description: format!("{verb} {}", file.name), changes: vec![change] };
+        self.apply(file.collection, step).map_err(|err| err.to_string())
+    }
+
+    fn remove_file(&self, node: Node) -> Result<(), String> {
+        let Node::File(collection, kind, name) = node else {
+            return Err("Only recipes and products can be removed".to_string());
+        };
+        let repo = self.collections[collection].repo.snapshot();
+        let change = match kind {
+            Kind::Recipes => Change::recipe(&repo, &name, None),
+            Kind::Products => {
+                check_unused(&repo, &name)?;
+                Change::product(&repo, &name, None)
+            }
+        };
+        let step = Step { description: format!("Remove {name}"), changes: vec![change] };
+        self.apply(collection, step).map_err(|err| err.to_string())
+    }
+
+    fn rename_file(&self, from: Node, to: Node) -> Result<(), String> {
+        let (Node::File(collection, kind, old), Node::File(to_collection, to_kind, new)) = (from, to) else {
+            return Err("Only recipes and products can be renamed".to_string());
+        };
+        if (collection, kind) != (to_collection, to_kind) {
+            return Err(format!("{old} can only be renamed within its folder"));
+        }
+        let repo = self.collections[collection].repo.snapshot();
+        let taken = match kind {
+            Kind::Recipes => repo.recipes.contains_key(&new),
+            Kind::Products => repo.ingredients.contains_key(&new),
+        };
+        if taken {
+            return Err(AppError::NameTaken(new.clone()).to_string());
+        }
+        let changes = match kind {
+            Kind::Recipes => {
+                let recipe = repo.recipes.get(&old).ok_or(format!("There is no recipe {old}"))?;
+                let renamed = Recipe { name: new.clone(), ..recipe.clone() };
+                vec![Change::recipe(&repo, &old, None), Change::recipe(&repo, &new, Some(renamed))]
+            }
+            Kind::Products => {
+                check_unused(&repo, &old)?;
+                let product = repo.ingredients.get(&old).ok_or(format!("There is no product {old}"))?;
+                let renamed = Product { name: new.clone(), ..product.clone() };
+                vec![Change::product(&repo, &old, None), Change::product(&repo, &new, Some(renamed))]
+            }
+        };
+        let step = Step { description: format!("Rename {old} to {new}"), changes };
+        self.apply(collection, step).map_err(|err| err.to_string())
+    }
+}
+
+/// The recipe in `table`, with its products taken from `repo`.
+fn parse_recipe(table: toml::Table, repo: &Reposotory) -> Result<Recipe, String> {
+    let recipe: DumbRecipe = table.try_into().map_err(|err| format!("Invalid recipe: {err}"))?;
+    if let Some((_, product)) = recipe.ingredients.iter().find(|(_, product)| !repo.ingredients.contains_key(product)) {
+        return Err(format!("Unknown product {product:?}, upload products/{product}.toml first"));
+    }
+    repo.enrich(recipe).ok_or_else(|| "The recipe uses unknown products".to_string())
+}
+
+/// Fails when a recipe still uses the product `name`.
+fn check_unused(repo: &Reposotory, name: &str) -> Result<(), String> {
+    let user = repo
+        .recipes
+        .values()
+        .find(|recipe| recipe.ingredients.iter().any(|(_, product)| product.name == name));
+    match user {
+        Some(recipe) => Err(format!("{name} is still used by {}", recipe.name)),
+        None => Ok(()),
+    }
+}
+
+/// The parts of `path`, with `.` and `..` taken care of.
+fn normalize(path: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts
+}
+
+fn folder() -> FileAttributes {
+    FileAttributes { permissions: Some(0o40755), ..FileAttributes::empty() }
+}
+
+fn file(size: usize, writable: bool) -> FileAttributes {
+    let permissions = if writable { 0o100644 } else { 0o100444 };
+    FileAttributes { size: Some(size as u64), permissions: Some(permissions), ..FileAttributes::empty() }
+}
+
+fn status(id: u32, result: Result<(), String>) -> Status {
+    let (status_code, error_message) = match result {
+        Ok(()) => (StatusCode::Ok, "Ok".to_string()),
+        Err(message) => {
+            tracing::info!("Rejected: {message}");
+            (StatusCode::Failure, message)
+        }
+    };
+    Status { id, status_code, error_message, language_tag: "en-US".to_string() }
+}
+
+impl russh_sftp::server::Handler for Sftp {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(&mut self, _: u32, _: HashMap<String, String>) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let path = format!("/{}", normalize(&path).join("/"));
+        Ok(Name { id, files: vec![File::dummy(path)] })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let attrs = self.attributes(&self.node(&path)?)?;
+        Ok(Attrs { id, attrs })
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        self.stat(id, path).await
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        let attrs = match self.opened.get(&handle) {
+            Some(Opened::File(file)) => self::file(file.data.len(), true),
+            Some(Opened::Folder(_)) => folder(),
+            None => return Err(StatusCode::Failure),
+        };
+        Ok(Attrs { id, attrs })
+    }
+
+    /// Times and permissions are made up, so there is nothing to set, but
+    /// clients like scp insist.
+    async fn setstat(&mut self, id: u32, _: String, _: FileAttributes) -> Result<Status, Self::Error> {
+        Ok(status(id, Ok(())))
+    }
+
+    async fn fsetstat(&mut self, id: u32, _: String, _: FileAttributes) -> Result<Status, Self::Error> {
+        Ok(status(id, Ok(())))
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        let files = self.list(&self.node(&path)?)?;
+        let handle = self.open_handle(Opened::Folder(Some(files)))?;
+        Ok(Handle { id, handle })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        match self.opened.get_mut(&handle) {
+            Some(Opened::Folder(files)) => files.take().map(|files| Name { id, files }).ok_or(StatusCode::Eof),
+            _ => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn open(&mut self, id: u32, filename: String, flags: OpenFlags, _: FileAttributes) -> Result<Handle, Self::Error> {
+        let Node::File(collection, kind, name) = self.node(&filename)? else {
+            return Err(StatusCode::Failure);
+        };
+        let writing = flags.intersects(OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE | OpenFlags::TRUNCATE);
+        if writing && !kind.writable(self.collections[collection].role) {
+            return Err(StatusCode::PermissionDenied);
+        }
+        let (data, changed) = match self.read_file(collection, kind, &name) {
+            Some(_) if flags.contains(OpenFlags::CREATE | OpenFlags::EXCLUDE) => return Err(StatusCode::Failure),
+            Some(_) if flags.contains(OpenFlags::TRUNCATE) => (Vec::new(), true),
+            Some(data) => (data, false),
+            None if flags.contains(OpenFlags::CREATE) => (Vec::new(), true),
+            None => return Err(StatusCode::NoSuchFile),
+        };
+        let handle = self.open_handle(Opened::File(OpenFile { collection, kind, name, data, changed }))?;
+        Ok(Handle { id, handle })
+    }
+
+    async fn read(&mut self, id: u32, handle: String, offset: u64, len: u32) -> Result<Data, Self::Error> {
+        let Some(Opened::File(file)) = self.opened.get(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        if start >= file.data.len() {
+            return Err(StatusCode::Eof);
+        }
+        let end = file.data.len().min(start.saturating_add(len as usize));
+        Ok(Data { id, data: file.data[start..end].to_vec() })
+    }
+
+    async fn write(&mut self, id: u32, handle: String, offset: u64, data: Vec<u8>) -> Result<Status, Self::Error> {
+        let Some(Opened::File(file)) = self.opened.get_mut(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        let start = usize::try_from(offset).map_err(|_| StatusCode::Failure)?;
+        let end = start.checked_add(data.len()).filter(|&end| end <= MAX_FILE).ok_or(StatusCode::Failure)?;
+        if file.data.len() < end {
+            file.data.resize(end, 0);
+        }
+        file.data[start..end].copy_from_slice(&data);
+        file.changed = true;
+        Ok(status(id, Ok(())))
+    }
+
+    /// Uploads are only saved once they are complete.
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        match self.opened.remove(&handle) {
+            Some(Opened::File(file)) if file.changed => Ok(status(id, self.save(&file))),
+            Some(_) => Ok(status(id, Ok(()))),
+            None => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+        let node = self.node(&filename)?;
+        Ok(status(id, self.remove_file(node)))
+    }
+
+    async fn rename(&mut self, id: u32, oldpath: String, newpath: String) -> Result<Status, Self::Error> {
+        let (from, to) = (self.node(&oldpath)?, self.node(&newpath)?);
+        Ok(status(id, self.rename_file(from, to)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use calicomp::{app::shared::SharedRepo, sys::db};
+    use russh_sftp::server::Handler;
+    use tokio::sync::Semaphore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_handles_past_the_limit() {
+        let house = Collection::house(SharedRepo::new(db::demo()), Role::Guest);
+        let permit = Arc::new(Semaphore::new(1)).try_acquire_owned().unwrap();
+        let mut sftp = Sftp::new("alice", vec![house], permit);
+
+        let mut handles = Vec::new();
+        for id in 0..MAX_HANDLES as u32 {
+            handles.push(sftp.opendir(id, "/house/recipes".to_string()).await.unwrap().handle);
+        }
+        assert_eq!(sftp.opendir(0, "/house".to_string()).await.unwrap_err(), StatusCode::Failure);
+
+        // Closing one makes room again.
+        sftp.close(0, handles.pop().unwrap()).await.unwrap();
+        sftp.opendir(0, "/house".to_string()).await.unwrap();
+    }
+}
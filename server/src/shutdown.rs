@@ -0,0 +1,71 @@
+//! Going down without leaving anyone behind in a broken terminal. Once it
+//! begins, every session shows how long it has left, and hangs up on its
+//! own when the time is up.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<Option<Instant>>>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::Sender::new(None)))
+    }
+
+    /// Tell every session the server goes down in `grace`.
+    pub fn begin(&self, grace: Duration) {
+        self.0.send_replace(Some(Instant::now() + grace));
+    }
+
+    pub fn is_begun(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    /// Wait for the shutdown to begin, and tell when the server goes down.
+    pub async fn begun(&self) -> Instant {
+        let mut deadline = self.0.subscribe();
+        let begun = deadline.wait_for(Option::is_some).await.map(|deadline| *deadline);
+        match begun {
+            Ok(deadline) => deadline.expect("waited for it to be set"),
+            // The sender lives in `self`, so this can't happen.
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What sessions show while the server goes down at `deadline`.
+pub fn banner(deadline: Instant) -> String {
+    let left = deadline.saturating_duration_since(Instant::now());
+    // Round up, so it never says 0 while there is still time.
+    let seconds = left.as_millis().div_ceil(1000);
+    format!("Server going down in {seconds} seconds, save your work")
+}
+
+/// Resolves on the first SIGINT or SIGTERM.
+pub async fn signal() {
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                tracing::warn!("Can't listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        () = terminate => {}
+    }
+}
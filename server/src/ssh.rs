@@ -3,20 +3,31 @@
 // https://github.com/Eugeny/russh/blob/main/russh/examples/ratatui_app.rs
 
 
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io::Write;
+use std::time::Duration;
 use std::collections::HashMap;
 
 use async_trait::async_trait;
-use crossterm::event::Event;
+use crossterm::{
+    cursor::{MoveTo, Show},
+    event::{DisableMouseCapture, EnableMouseCapture, Event},
+    execute,
+    style::ResetColor,
+    terminal::{Clear, ClearType},
+};
 use ed25519_dalek::{pkcs8::{spki::der::pem::LineEnding::LF, DecodePrivateKey, EncodePrivateKey}, SigningKey};
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::Rect;
-use ratatui::Terminal;
+use ratatui::{Terminal, TerminalOptions, Viewport};
 use russh::keys::key::PublicKey;
 use russh::server::*;
-use russh::{Channel, ChannelId};
+use russh::{Channel, ChannelId, CryptoVec};
 use russh_keys::key::KeyPair;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
 
 type SshTerminal = Terminal<CrosstermBackend<TerminalHandle>>;
 
@@ -31,28 +42,64 @@ struct TerminalHandle {
 struct Instance {
     terminal: SshTerminal,
     app: App,
+    dispatch: Dispatch,
+    /// Counts the session towards the most that may be open at once.
+    _permit: OwnedSemaphorePermit,
 }
 
 impl Instance {
-    pub async fn step(&mut self, event: Event) -> anyhow::Result<bool> {
-        update(&mut self.app, event).await.unwrap();
-        self.terminal.draw(|frame| ui::entry(frame, &mut self.app))?;
+    /// Handle `event`, returning whether the session is over. Nothing that
+    /// goes wrong here ends it, it is reported to the user instead.
+    pub async fn step(&mut self, event: Event) -> bool {
+        let result = update(&mut self.app, event).with_subscriber(self.dispatch.clone()).await;
+        tracing::dispatcher::with_default(&self.dispatch, || {
+            if let Err(err) = result {
+                self.app.report(&err);
+            }
+            // There is no local terminal to run an editor in.
+            if std::mem::take(&mut self.app.open_in_editor) {
+                self.app.report(&AppError::Unsupported("Editing in $EDITOR"));
+            }
+        });
+        if let Err(err) = self.draw_state().await {
+            tracing::dispatcher::with_default(&self.dispatch, || self.app.report(&AppError::Draw(err.to_string())));
+        }
         if self.app.should_quit {
-            Ok(true)
-        } else {
-            Ok(false)
+            // The client is told to leave the mouse be when hanging up, too.
+            let _ = execute!(self.terminal.backend_mut(), DisableMouseCapture);
         }
+        self.app.should_quit
     }
 
     pub async fn draw_state(&mut self) -> anyhow::Result<()> {
-        self.terminal.draw(|frame| ui::entry(frame, &mut self.app))?;
+        let Instance { terminal, app, dispatch, .. } = self;
+        tracing::dispatcher::with_default(dispatch, || terminal.draw(|frame| ui::entry(frame, app)))?;
+        Ok(())
+    }
+
+    /// Leave the client's terminal the way it was, say why, and hang up.
+    pub async fn leave(&mut self, reason: &str) -> anyhow::Result<()> {
+        let backend = self.terminal.backend_mut();
+        execute!(backend, DisableMouseCapture, ResetColor, Clear(ClearType::All), MoveTo(0, 0), Show)?;
+        backend.write_all(format!("{reason}\r\n").as_bytes())?;
+        backend.flush()?;
+        let TerminalHandle { handle, channel_id, .. } = backend.writer();
+        // The client may be gone already, then there is nobody to tell.
+        let _ = handle.exit_status_request(*channel_id, 0).await;
+        let _ = handle.eof(*channel_id).await;
+        let _ = handle.close(*channel_id).await;
         Ok(())
     }
 }
 
-use calicomp::{app::{events::{self, update}, App}, tui::EventHandler, ui};
+use calicomp::{app::{error::AppError, events::{self, update}, log::Log, shared::Collection, App}, tui::EventHandler, ui::{self, photo::PhotoState, theme::Theme}};
+use tracing::{instrument::WithSubscriber, Dispatch};
 
-use crate::parser;
+use crate::{exec, parser};
+use crate::config::SshConfig;
+use crate::sftp::Sftp;
+use crate::shutdown::{self, Shutdown};
+use crate::users::{Login, Users};
 
 // The crossterm backend writes to the terminal handle.
 impl std::io::Write for TerminalHandle {
@@ -77,60 +124,229 @@ impl std::io::Write for TerminalHandle {
     }
 }
 
+/// The terminal a client asked for with a pty request, before its shell
+/// starts.
+#[derive(Clone)]
+struct ClientTerminal {
+    term: String,
+    area: Rect,
+    /// Of one cell in pixels, when the client told.
+    font_size: Option<(u16, u16)>,
+}
+
+impl Default for ClientTerminal {
+    /// What we assume for clients that never asked for a pty.
+    fn default() -> Self {
+        Self { term: String::new(), area: crate::terminal_area(80, 24), font_size: None }
+    }
+}
+
+/// A shell is told apart by the connection it came over, and its channel
+/// on that connection.
+type SessionId = (usize, ChannelId);
+
+type Clients = Arc<Mutex<HashMap<SessionId, Instance>>>;
+
 #[derive(Clone)]
 pub struct AppServer {
-    clients: Arc<Mutex<HashMap<usize, Instance>>>,
+    clients: Clients,
+    users: Users,
+    /// Shared with the web frontend.
+    sessions: Arc<Semaphore>,
+    shutdown: Shutdown,
+    /// Who logged in on this connection, once they have.
+    login: Option<Login>,
+    /// Hands out connection ids, the last one given out.
+    next_id: Arc<AtomicUsize>,
     id: usize,
+    /// Channels opened on this connection that nothing runs on yet, behind
+    /// a lock only so the handler can be cloned.
+    channels: Arc<Mutex<HashMap<ChannelId, Channel<Msg>>>>,
+    /// Terminals asked for on this connection whose shells haven't started.
+    terminals: HashMap<ChannelId, ClientTerminal>,
+    /// Cleans up after this connection when it goes away.
+    _connection: Option<Arc<Connection>>,
+}
+
+/// Removes the shells of a connection once it is gone, however it went.
+struct Connection {
+    id: usize,
+    clients: Clients,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let (id, clients) = (self.id, self.clients.clone());
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        runtime.spawn(async move {
+            let mut clients = clients.lock().await;
+            let before = clients.len();
+            clients.retain(|(connection, _), _| *connection != id);
+            if clients.len() < before {
+                tracing::info!(connection = id, "Dropped {} shells of a closed connection", before - clients.len());
+            }
+        });
+    }
 }
 
 impl AppServer {
-    pub fn new() -> Self {
+    pub fn new(users: Users, sessions: Arc<Semaphore>, shutdown: Shutdown) -> Self {
         Self {
             clients: Arc::new(Mutex::new(HashMap::new())),
+            users,
+            sessions,
+            shutdown,
+            login: None,
+            next_id: Arc::new(AtomicUsize::new(0)),
             id: 0,
+            channels: Arc::default(),
+            terminals: HashMap::new(),
+            _connection: None,
+        }
+    }
+
+    /// Redraw the shell `id` whenever one of its collections changes or a
+    /// toast in its `log` goes away, until it is gone, or the server goes
+    /// down.
+    async fn watch_shell(id: SessionId, collections: Vec<Collection>, log: Log, clients: Clients, shutdown: Shutdown) {
+        let mut updates: Vec<_> = collections.iter().map(|collection| collection.repo.subscribe()).collect();
+        loop {
+            let changes = updates.iter_mut().map(|updates| Box::pin(updates.changed()));
+            // Whether a collection changed, rather than a toast going away.
+            let changed = tokio::select! {
+                (changed, _, _) = futures::future::select_all(changes) => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    true
+                }
+                () = crate::toasts_expire(&log) => false,
+                // Look again when the next toast goes away.
+                () = log.toasted() => continue,
+                deadline = shutdown.begun() => {
+                    return Self::count_down(id, deadline, clients).await;
+                }
+            };
+            let mut clients = clients.lock().await;
+            let Some(instance) = clients.get_mut(&id) else {
+                return;
+            };
+            if !changed || instance.app.sync() {
+                if let Err(err) = instance.draw_state().await {
+                    tracing::warn!("Failed to redraw after a change: {err}");
+                }
+            }
+        }
+    }
+
+    /// Show the shell `id` how long it has until `deadline`, then hang up.
+    async fn count_down(id: SessionId, deadline: Instant, clients: Clients) {
+        loop {
+            let mut clients = clients.lock().await;
+            let Some(instance) = clients.get_mut(&id) else {
+                return;
+            };
+            if Instant::now() >= deadline {
+                let mut instance = clients.remove(&id).expect("just looked it up");
+                drop(clients);
+                if let Err(err) = instance.leave("The server went down").await {
+                    tracing::warn!("Failed to leave a terminal behind cleanly: {err}");
+                }
+                return;
+            }
+            instance.app.banner = Some(shutdown::banner(deadline));
+            if let Err(err) = instance.draw_state().await {
+                tracing::warn!("Failed to show the shutdown banner: {err}");
+            }
+            drop(clients);
+            tokio::time::sleep_until(deadline.min(Instant::now() + Duration::from_secs(1))).await;
+        }
+    }
+
+    /// Forget the shell on `channel`.
+    async fn forget(&mut self, channel: ChannelId) {
+        self.channels.lock().await.remove(&channel);
+        self.terminals.remove(&channel);
+        if self.clients.lock().await.remove(&(self.id, channel)).is_some() {
+            tracing::info!(connection = self.id, ?channel, "Shell closed");
+        }
+    }
+
+    /// Forget the shell on `channel`, and hang up on it.
+    async fn close(&mut self, channel: ChannelId, session: &mut Session) {
+        self.forget(channel).await;
+        hang_up(channel, 0, session);
+    }
+
+    /// Who logged in on this connection. Channels only open once the key
+    /// was accepted.
+    fn login(&self) -> Login {
+        self.login.clone().unwrap_or(Login::Guest)
+    }
+
+    /// Count one more session, be it a shell, a command or sftp, so the
+    /// server waits for it when going down. Says why when it can't.
+    fn admit(&self, what: &str) -> Result<OwnedSemaphorePermit, &'static str> {
+        if self.shutdown.is_begun() {
+            return Err("The server is going down");
         }
+        self.sessions.clone().try_acquire_owned().map_err(|_| {
+            tracing::warn!("Turned away {what}, too many sessions are open");
+            "Too many sessions are open, try again later"
+        })
     }
 
-    pub async fn run(&mut self) -> Result<(), anyhow::Error> {
-        let key = if let Ok(keyfile) = tokio::fs::read_to_string("./keypair").await {
+    /// Serve ssh as set up in `config`, with the host key at `host_key`.
+    pub async fn run(&mut self, config: &SshConfig, host_key: &Path) -> Result<(), anyhow::Error> {
+        let key = if let Ok(keyfile) = tokio::fs::read_to_string(host_key).await {
             tracing::info!("Loaded private key");
             let key = SigningKey::from_pkcs8_pem(&keyfile)?; 
             KeyPair::Ed25519(key)
         } else {
-            tracing::info!("No keypair found at './keypair', generating new");
+            tracing::info!("No keypair found at {}, generating new", host_key.display());
             let key = KeyPair::generate_ed25519().unwrap();
             let KeyPair::Ed25519(k) = &key else { panic!() };
             let res = SigningKey::to_pkcs8_pem(k, LF)?;
-            tokio::fs::write("keypair", res).await?;
+            tokio::fs::write(host_key, res).await?;
             key
         };
 
-        let config = Config {
-            inactivity_timeout: Some(std::time::Duration::from_secs(3600)),
-            auth_rejection_time: std::time::Duration::from_secs(3),
+        let russh_config = Config {
+            inactivity_timeout: config.inactivity_timeout(),
+            auth_rejection_time: std::time::Duration::from_secs(config.auth_rejection_time),
             auth_rejection_time_initial: Some(std::time::Duration::from_secs(0)),
             keys: vec![key],
             ..Default::default()
         };
 
-        self.run_on_address(Arc::new(config), ("0.0.0.0", 2222))
+        tracing::info!(addr = %config.addr(), "Serving ssh");
+        self.run_on_address(Arc::new(russh_config), config.addr())
             .await?;
         Ok(())
     }
 }
 
-impl Default for AppServer {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Tell the client how it went on `channel`, and that nothing more comes.
+fn hang_up(channel: ChannelId, status: u32, session: &mut Session) {
+    session.exit_status_request(channel, status);
+    session.eof(channel);
+    session.close(channel);
 }
 
 impl Server for AppServer {
     type Handler = Self;
     fn new_client(&mut self, _: Option<std::net::SocketAddr>) -> Self {
-        let s = self.clone();
-        self.id += 1;
-        s
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        Self {
+            id,
+            login: None,
+            channels: Arc::default(),
+            terminals: HashMap::new(),
+            _connection: Some(Arc::new(Connection { id, clients: self.clients.clone() })),
+            ..self.clone()
+        }
     }
 }
 
@@ -138,62 +354,219 @@ impl Server for AppServer {
 impl Handler for AppServer {
     type Error = anyhow::Error;
 
-    #[tracing::instrument(skip_all)]
     async fn channel_open_session(
         &mut self,
         channel: Channel<Msg>,
-        session: &mut Session,
+        _: &mut Session,
     ) -> Result<bool, Self::Error> {
-        {
-            let mut clients = self.clients.lock().await;
-            let terminal_handle = TerminalHandle {
-                handle: session.handle(),
-                sink: Vec::new(),
-                channel_id: channel.id(),
-            };
+        // Kept for subsystems, which talk over the channel themselves.
+        self.channels.lock().await.insert(channel.id(), channel);
+        Ok(true)
+    }
 
-            let backend = CrosstermBackend::new(terminal_handle.clone());
-            let mut terminal = Terminal::new(backend)?;
-            let app = App::new();
+    /// Start the app on `channel`, a connection can have several going.
+    #[tracing::instrument(skip_all, fields(connection = self.id, ?channel))]
+    async fn shell_request(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let permit = match self.admit("a shell") {
+            Ok(permit) => permit,
+            Err(reason) => {
+                session.data(channel, CryptoVec::from_slice(format!("{reason}\r\n").as_bytes()));
+                hang_up(channel, 1, session);
+                return Ok(());
+            }
+        };
+        let mut clients = self.clients.lock().await;
+        let terminal_handle = TerminalHandle {
+            handle: session.handle(),
+            sink: Vec::new(),
+            channel_id: channel,
+        };
 
-            tracing::info!("Got new terminal");
+        // Without a pty there is no size to go by, nor a TERM.
+        let client = self.terminals.remove(&channel).unwrap_or_default();
+        let backend = CrosstermBackend::new(terminal_handle.clone());
+        // The size of the server's own terminal has nothing to do with the
+        // client's, so never ask for it.
+        let mut terminal = Terminal::with_options(backend, TerminalOptions { viewport: Viewport::Fixed(client.area) })?;
+        let log = Log::default();
+        let dispatch = crate::session_dispatch(&log);
+        let login = self.login();
+        let collections = self.users.collections(&login);
+        let id = (self.id, channel);
+        tokio::spawn(Self::watch_shell(id, collections.clone(), log.clone(), self.clients.clone(), self.shutdown.clone()));
+        let app = tracing::dispatcher::with_default(&dispatch, || {
+            let mut app = App::with_collections(login.name(), collections, log);
+            let var = |name: &str| (name == "TERM" && !client.term.is_empty()).then(|| client.term.clone());
+            match Theme::load_for(var) {
+                Ok(theme) => app.theme = theme,
+                Err(err) => tracing::warn!("Could not load theme: {err}"),
+            }
+            app.photo = PhotoState::for_terminal(&client.term, client.font_size);
+            app
+        });
 
-            terminal.clear()?;
+        tracing::info!(term = client.term, size = ?client.area.as_size(), "Got new terminal");
 
-            let mut instance = Instance { terminal, app };
-            instance.draw_state().await?;
-            clients.insert(self.id, instance);
+        // Every write to the handle waits for the session, which is busy
+        // running this very request, so clear the screen in one go rather
+        // than row by row like `Terminal::clear` does for a fixed viewport.
+        execute!(terminal.backend_mut(), Clear(ClearType::All), EnableMouseCapture)?;
+
+        let mut instance = Instance { terminal, app, dispatch, _permit: permit };
+        instance.draw_state().await?;
+        clients.insert(id, instance);
+        Ok(())
+    }
+
+    /// Note down the size and kind of terminal the client has, for when its
+    /// shell starts.
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        term: &str,
+        col_width: u32,
+        row_height: u32,
+        pix_width: u32,
+        pix_height: u32,
+        _: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let cell = |pixels: u32, cells: u32| u16::try_from(pixels / cells).unwrap_or(u16::MAX);
+        let font_size = (col_width > 0 && row_height > 0 && pix_width > 0 && pix_height > 0)
+            .then(|| (cell(pix_width, col_width), cell(pix_height, row_height)));
+        self.terminals.insert(channel, ClientTerminal { term: term.to_string(), area: crate::terminal_area(col_width, row_height), font_size });
+        session.channel_success(channel);
+        Ok(())
+    }
+
+    /// Run a command like `list` or `export`, print what it has to say and
+    /// hang up.
+    #[tracing::instrument(skip_all, fields(connection = self.id, ?channel))]
+    async fn exec_request(
+        &mut self,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // `ssh -t` asks for a pty first, which nothing draws on.
+        self.terminals.remove(&channel);
+        let _permit = match self.admit("a command") {
+            Ok(permit) => permit,
+            Err(reason) => {
+                session.extended_data(channel, 1, CryptoVec::from_slice(format!("{reason}\n").as_bytes()));
+                hang_up(channel, 1, session);
+                return Ok(());
+            }
+        };
+        let command = String::from_utf8_lossy(data);
+        tracing::info!(login = self.login().name(), %command, "Running command");
+        let output = exec::run(&command, &self.users.house().snapshot());
+        if !output.stdout.is_empty() {
+            session.data(channel, output.stdout.into());
+        }
+        if !output.stderr.is_empty() {
+            // Extended data of type 1 is stderr.
+            session.extended_data(channel, 1, output.stderr.into());
         }
+        hang_up(channel, output.status, session);
+        Ok(())
+    }
 
-        Ok(true)
+    /// Serve the collections of whoever logged in as files, for `sftp`, `scp`
+    /// and `sshfs`.
+    #[tracing::instrument(skip_all, fields(connection = self.id, ?channel))]
+    async fn subsystem_request(
+        &mut self,
+        channel: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let Some(stream) = self.channels.lock().await.remove(&channel).filter(|_| name == "sftp") else {
+            tracing::info!(name, "Refused subsystem");
+            session.channel_failure(channel);
+            return Ok(());
+        };
+        let permit = match self.admit("sftp") {
+            Ok(permit) => permit,
+            Err(reason) => {
+                tracing::info!(reason, "Refused sftp");
+                session.channel_failure(channel);
+                return Ok(());
+            }
+        };
+        let login = self.login();
+        tracing::info!(login = login.name(), "Started sftp");
+        session.channel_success(channel);
+        let collections = self.users.collections(&login);
+        russh_sftp::server::run(stream.into_stream(), Sftp::new(login.name(), collections, permit)).await;
+        Ok(())
+    }
+
+    async fn channel_eof(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.close(channel, session).await;
+        Ok(())
+    }
+
+    /// The client hung up, so there is nobody left to tell.
+    async fn channel_close(
+        &mut self,
+        channel: ChannelId,
+        _: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.forget(channel).await;
+        Ok(())
     }
 
-    async fn auth_publickey(&mut self, _: &str, _: &PublicKey) -> Result<Auth, Self::Error> {
-        Ok(Auth::Accept)
+    async fn auth_publickey(&mut self, _: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        match self.users.login(key) {
+            Some(login) => {
+                tracing::info!(?login, "Logged in");
+                self.login = Some(login);
+                Ok(Auth::Accept)
+            }
+            None => {
+                tracing::info!(fingerprint = key.fingerprint(), "Rejected unknown key");
+                Ok(Auth::Reject { proceed_with_methods: None })
+            }
+        }
     }
 
     async fn data(
         &mut self,
-        _channel: ChannelId,
+        channel: ChannelId,
         data: &[u8],
-        _session: &mut Session,
+        session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let mut lock = self.clients.lock().await;
-        let event = parser::parse_event(data, false)?;
-        if let Some(event) = event {
-            let instance = lock.get_mut(&self.id).unwrap();
-            let should_quit = instance.step(event).await?;
+        let mut clients = self.clients.lock().await;
+        let Some(instance) = clients.get_mut(&(self.id, channel)) else {
+            return Ok(());
+        };
+        let mut should_quit = false;
+        for event in parser::parse_events(data) {
+            should_quit = instance.step(event).await;
             if should_quit {
-                lock.remove(&self.id);
+                break;
             }
         }
+        drop(clients);
+        if should_quit {
+            self.close(channel, session).await;
+        }
         Ok(())
     }
 
     /// The client's window size has changed.
     async fn window_change_request(
         &mut self,
-        _: ChannelId,
+        channel: ChannelId,
         col_width: u32,
         row_height: u32,
         _: u32,
@@ -203,17 +576,11 @@ impl Handler for AppServer {
         tracing::debug!("resized to {row_height} x {col_width}");
         {
             let mut clients = self.clients.lock().await;
-            let instance = clients.get_mut(&self.id).unwrap();
-
-            let width = col_width.min(255);
-            let height = row_height.min(255);
-            let rect = Rect {
-                x: 0,
-                y: 0,
-                width: width as u16,
-                height: height as u16,
+            let Some(instance) = clients.get_mut(&(self.id, channel)) else {
+                return Ok(());
             };
-            instance.terminal.resize(rect)?;
+
+            instance.terminal.resize(crate::terminal_area(col_width, row_height))?;
             instance.draw_state().await?;
         }
 
@@ -221,3 +588,96 @@ impl Handler for AppServer {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use calicomp::{app::shared::SharedRepo, sys::db};
+    use russh::{client, Disconnect};
+
+    use super::*;
+
+    struct Client;
+
+    #[async_trait]
+    impl client::Handler for Client {
+        type Error = anyhow::Error;
+
+        async fn check_server_key(&mut self, _: &PublicKey) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    /// A server letting guests in on a free port, and the shells it has open.
+    async fn serve() -> (std::net::SocketAddr, Clients) {
+        let users = Users::new(SharedRepo::new(db::demo()), Vec::new(), true, std::env::temp_dir());
+        let mut server = AppServer::new(users, Arc::new(Semaphore::new(10)), Shutdown::new());
+        let clients = server.clients.clone();
+        let config = Arc::new(Config { keys: vec![KeyPair::generate_ed25519().unwrap()], ..Default::default() });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server.run_on_socket(config, &listener).await });
+        (addr, clients)
+    }
+
+    async fn connect(addr: std::net::SocketAddr) -> client::Handle<Client> {
+        let mut handle = client::connect(Arc::new(client::Config::default()), addr, Client).await.unwrap();
+        let key = Arc::new(KeyPair::generate_ed25519().unwrap());
+        assert!(handle.authenticate_publickey("guest", key).await.unwrap());
+        handle
+    }
+
+    /// Start a shell on a new channel of `handle`, and wait for the server to
+    /// have `count` open. Returns the one it started.
+    async fn open_shell(handle: &client::Handle<Client>, clients: &Clients, count: usize) -> (Channel<client::Msg>, SessionId) {
+        let before = shells(clients, count - 1).await;
+        let channel = handle.channel_open_session().await.unwrap();
+        channel.request_pty(false, "xterm", 80, 24, 0, 0, &[]).await.unwrap();
+        channel.request_shell(false).await.unwrap();
+        let id = shells(clients, count).await.into_iter().find(|id| !before.contains(id)).unwrap();
+        (channel, id)
+    }
+
+    /// The shells the server has open, once there are `count` of them.
+    async fn shells(clients: &Clients, count: usize) -> Vec<SessionId> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let ids: Vec<SessionId> = clients.lock().await.keys().copied().collect();
+            if ids.len() == count {
+                return ids;
+            }
+            assert!(Instant::now() < deadline, "expected {count} shells, the server has {ids:?}");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn closing_a_shell_leaves_the_others_on_its_connection() {
+        let (addr, clients) = serve().await;
+        let handle = connect(addr).await;
+        let (first, first_id) = open_shell(&handle, &clients, 1).await;
+        let (second, second_id) = open_shell(&handle, &clients, 2).await;
+        assert_eq!(first_id.0, second_id.0);
+        assert_ne!(first_id.1, second_id.1);
+
+        first.close().await.unwrap();
+        assert_eq!(shells(&clients, 1).await, vec![second_id]);
+
+        let (_third, third_id) = open_shell(&handle, &clients, 2).await;
+        second.eof().await.unwrap();
+        assert_eq!(shells(&clients, 1).await, vec![third_id]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_closed_connection_takes_only_its_own_shells() {
+        let (addr, clients) = serve().await;
+        let staying = connect(addr).await;
+        let (_kept, kept_id) = open_shell(&staying, &clients, 1).await;
+
+        let leaving = connect(addr).await;
+        let (_first, _) = open_shell(&leaving, &clients, 2).await;
+        let (_second, _) = open_shell(&leaving, &clients, 3).await;
+        leaving.disconnect(Disconnect::ByApplication, "", "en").await.unwrap();
+        drop(leaving);
+        assert_eq!(shells(&clients, 1).await, vec![kept_id]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Who may log in over ssh, and which collections they get.
+//!
+//! Keys are listed in `authorized_keys`, in the format OpenSSH uses, with
+//! the name of the user as the comment, and then their role in the house
+//! collection when it isn't `bartender`:
+//!
+//! ```text
+//! ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ alice manager
+//! ```
+//!
+//! Everyone sees the house collection, and each user also gets one of their
+//! own that nobody else sees, kept in a file named after them. That is why
+//! nobody can be called `house`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use calicomp::app::{role::Role, shared::{Collection, SharedRepo}};
+use calicomp::sys::data::Reposotory;
+use russh::keys::key::PublicKey;
+
+/// Who a session belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Login {
+    User { name: String, role: Role },
+    /// Someone whose key isn't listed, let in to look at the house recipes.
+    Guest,
+}
+
+impl Login {
+    /// The name changes are recorded under.
+    pub fn name(&self) -> &str {
+        match self {
+            Login::User { name, .. } => name,
+            Login::Guest => "guest",
+        }
+    }
+}
+
+/// A key allowed in, and who it belongs to.
+#[derive(Clone)]
+pub struct AuthorizedKey {
+    pub key: PublicKey,
+    pub name: String,
+    pub role: Role,
+}
+
+#[derive(Clone)]
+pub struct Users {
+    keys: Vec<AuthorizedKey>,
+    /// Whether unknown keys get a read only session instead of none.
+    guests: bool,
+    house: SharedRepo,
+    own: Arc<Mutex<HashMap<String, SharedRepo>>>,
+    /// Where the collections of the users are kept.
+    dir: PathBuf,
+}
+
+impl Users {
+    pub fn new(house: SharedRepo, keys: Vec<AuthorizedKey>, guests: bool, dir: PathBuf) -> Self {
+        Self {
+            keys,
+            guests,
+            house,
+            own: Arc::new(Mutex::new(HashMap::new())),
+            dir,
+        }
+    }
+
+    /// Read the keys allowed in from `path`. Nobody is allowed in when the
+    /// file doesn't exist, apart from guests. The collections of the users
+    /// are in `dir`.
+    pub fn load(path: &Path, house: SharedRepo, guests: bool, dir: PathBuf) -> anyhow::Result<Self> {
+        let keys = match std::fs::read_to_string(path) {
+            Ok(text) => parse_authorized_keys(&text),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!("No {} found, only guests can log in", path.display());
+                Vec::new()
+            }
+            Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+        };
+        tracing::info!("Loaded {} authorized keys", keys.len());
+        Ok(Self::new(house, keys, guests, dir))
+    }
+
+    /// Who `key` belongs to, `None` when they may not log in at all.
+    pub fn login(&self, key: &PublicKey) -> Option<Login> {
+        match self.keys.iter().find(|known| known.key == *key) {
+            Some(known) => Some(Login::User { name: known.name.clone(), role: known.role }),
+            None if self.guests => Some(Login::Guest),
+            None => None,
+        }
+    }
+
+    /// The collection everyone shares.
+    pub fn house(&self) -> &SharedRepo {
+        &self.house
+    }
+
+    /// The collections a session of `login` works on, the one it opens
+    /// first at the front.
+    pub fn collections(&self, login: &Login) -> Vec<Collection> {
+        match login {
+            Login::User { name, role } => {
+                let own = self.own(name);
+                vec![
+                    Collection { name: name.clone(), repo: own, role: Role::Manager },
+                    Collection::house(self.house.clone(), *role),
+                ]
+            }
+            Login::Guest => vec![Collection::house(self.house.clone(), Role::Guest)],
+        }
+    }
+
+    /// The collection of `name`, as it was saved last, or started with the
+    /// products of the house so their recipes have something to use.
+    fn own(&self, name: &str) -> SharedRepo {
+        let mut own = self.own.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        own.entry(name.to_string())
+            .or_insert_with(|| {
+                let saved = crate::load_repo(&self.path(name)).unwrap_or_else(|err| {
+                    tracing::warn!("Could not load the collection of {name}: {err:#}");
+                    None
+                });
+                let saved = saved.unwrap_or_else(|| Reposotory {
+                    ingredients: self.house.snapshot().ingredients.clone(),
+                    ..Reposotory::default()
+                });
+                let repo = SharedRepo::with_journal(saved, crate::load_journal(&self.path(name)));
+                crate::keep_saved(&repo, self.path(name));
+                repo
+            })
+            .clone()
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.toml"))
+    }
+
+    /// Write the collections of everyone who logged in since the start.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let own = self.own.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (name, repo) in own.iter() {
+            crate::save_repo(repo, &self.path(name))?;
+        }
+        Ok(())
+    }
+}
+
+/// The keys in an `authorized_keys` file, with who they belong to. Lines
+/// that can't be read are skipped, and logged.
+pub fn parse_authorized_keys(text: &str) -> Vec<AuthorizedKey> {
+    let mut keys = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Options like `no-pty` may come before the key type.
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let Some(start) = fields.iter().position(|field| is_key_type(field)) else {
+            tracing::warn!("authorized_keys line {}: no key type", number + 1);
+            continue;
+        };
+        let (Some(base64), Some(name)) = (fields.get(start + 1), fields.get(start + 2)) else {
+            tracing::warn!("authorized_keys line {}: needs a key and a user name", number + 1);
+            continue;
+        };
+        // Names end up in file names, next to the journals of the others,
+        // and name the user's own collection next to the house one.
+        if name.contains('/') || name.starts_with('.') || name.ends_with(".journal") || *name == Collection::HOUSE {
+            tracing::warn!("authorized_keys line {}: {name:?} can't be a user name", number + 1);
+            continue;
+        }
+        let role = match fields.get(start + 3).map(|role| role.parse()) {
+            None => Role::Bartender,
+            Some(Ok(role)) => role,
+            Some(Err(err)) => {
+                tracing::warn!("authorized_keys line {}: {err}", number + 1);
+                continue;
+            }
+        };
+        match russh_keys::parse_public_key_base64(base64) {
+            Ok(key) => keys.push(AuthorizedKey { key, name: name.to_string(), role }),
+            Err(err) => tracing::warn!("authorized_keys line {}: {err}", number + 1),
+        }
+    }
+    keys
+}
+
+fn is_key_type(field: &str) -> bool {
+    field.starts_with("ssh-") || field.starts_with("ecdsa-sha2-")
+}
@@ -0,0 +1,82 @@
+use std::{fmt, path::PathBuf};
+
+use crate::app::role::Role;
+
+/// Why an action couldn't be done. None of these end the session, they are
+/// shown to the user and the app carries on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    /// A recipe uses a product the repository doesn't know.
+    UnknownProduct {
+        recipe: String,
+        product: String,
+    },
+    /// There is no product by this name.
+    NoProduct(String),
+    /// An amount for a product that couldn't be read, and why.
+    Amount(String),
+    EmptyName,
+    NameTaken(String),
+    /// The recipe only exists in the editor, not in the repository.
+    NotSaved(String),
+    Photo {
+        path: String,
+        reason: String,
+    },
+    Theme(String),
+    /// A house glass that couldn't be read, and why.
+    Glass(String),
+    UnknownGlass(String),
+    Export {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The user's role doesn't allow this.
+    Forbidden {
+        role: Role,
+        what: String,
+    },
+    /// Someone else changed this recipe or product since it was read.
+    Conflict(String),
+    /// The action needs something this frontend doesn't have, like a local
+    /// terminal to run `$EDITOR` in.
+    Unsupported(&'static str),
+    /// The screen couldn't be drawn, and why.
+    Draw(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::UnknownProduct { recipe, product } => {
+                write!(f, "{recipe} uses {product}, which is not a known product")
+            }
+            AppError::NoProduct(name) => write!(f, "There is no product called {name:?}"),
+            AppError::Amount(reason) => write!(f, "Could not read the amount: {reason}"),
+            AppError::EmptyName => write!(f, "A recipe needs a name"),
+            AppError::NameTaken(name) => write!(f, "There is already a recipe called {name:?}"),
+            AppError::NotSaved(name) => write!(f, "{name} is not saved yet"),
+            AppError::Photo { path, reason } => {
+                write!(f, "Could not attach photo {path:?}: {reason}")
+            }
+            AppError::Theme(reason) => write!(f, "Could not change theme: {reason}"),
+            AppError::Glass(reason) => write!(f, "Could not read the glass: {reason}"),
+            AppError::UnknownGlass(name) => write!(f, "There is no house glass called {name:?}"),
+            AppError::Export { path, reason } => {
+                write!(
+                    f,
+                    "Could not export recipes to {}: {reason}",
+                    path.display()
+                )
+            }
+            AppError::Forbidden { role, what } => write!(f, "A {role} can't {what}"),
+            AppError::Conflict(name) => {
+                write!(f, "{name} was changed in another session meanwhile")
+            }
+            AppError::Unsupported(what) => write!(f, "{what} is not available here"),
+            AppError::Draw(reason) => write!(f, "Could not draw the screen: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
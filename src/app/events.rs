@@ -1,111 +1,427 @@
-use std::{
-    fs::File,
-    io::{self, stdout, Read, Write},
+use std::path::Path;
+
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
 };
+use ratatui::layout::Position;
 
-use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
+use crate::app::{
+    error::AppError,
+    keymap::{Action, Lookup},
+    *,
 };
-use eyre::Result;
-use ratatui::{prelude::Backend, Terminal};
-use serde::{de::DeserializeOwned, Serialize};
 
-use crate::{app::*, tui::EventHandler, ui::entry};
+/// Handle one terminal event. An action that fails leaves the app as it
+/// was, the error is for the caller to show with [`App::report`].
+pub async fn update(app: &mut App, event: Event) -> Result<(), AppError> {
+    app.sync();
+    match event {
+        Event::Key(key) if key.kind == KeyEventKind::Press => on_key(app, key),
+        Event::Mouse(mouse) => on_mouse(app, mouse),
+        Event::Paste(text) => {
+            paste(app, &text);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
 
-pub async fn update(app: &mut App, event: Event) -> Result<()> {
-    if let Event::Key(key) = event {
-        if key.kind == event::KeyEventKind::Release {
-            // Skip events that are not KeyEventKind::Press
+fn on_key(app: &mut App, key: KeyEvent) -> Result<(), AppError> {
+    // Any key closes an overlay, the log can be scrolled first.
+    if let Some(overlay) = app.overlay {
+        if overlay != Overlay::Log || !scroll_log(app, key) {
+            app.overlay = None;
         }
-        match app.current_mode {
-            CurrentMode::Main => match key.code {
-                KeyCode::Char('e') => {
-                    app.current_mode = CurrentMode::Editing;
-                    app.currently_editing = Some(CurrentlyEditing::Name);
-                }
-                KeyCode::Char('q') => {
-                    app.current_mode = CurrentMode::Exiting;
-                }
-                KeyCode::Char('p') => {
-                    panic!("at the disco");
-                }
-                KeyCode::Char('v') => {
-                    todo!()
-                    // return edit_with_editor(terminal, &mut app.current_recipe)
-                }
-                KeyCode::Char('s') => {
-                    let Some(()) = app.save_current_recipe() else {
-                        return Ok(());
-                    };
-                    app.recipes.push(app.current_recipe.name.clone());
-                }
-                KeyCode::Char('j') | KeyCode::Down => {
-                    app.list_state.select_next();
-                }
-                KeyCode::Char('k') | KeyCode::Up => {
-                    app.list_state.select_previous();
-                }
-                KeyCode::Esc => {
-                    app.list_state.select(None);
-                }
-                KeyCode::Enter => {
-                    let Some(i) = app.list_state.selected() else {
-                        return Ok(());
-                    };
-                    let name = &app.recipes[i];
-                    app.current_recipe = app.repo.recipes[name].clone().dumb();
-                }
-                _ => {}
-            },
-            CurrentMode::Exiting => match key.code {
-                KeyCode::Char('y') => {
-                    app.should_quit = true;
+        return Ok(());
+    }
+
+    match app.keymap.press(app.current_mode, key) {
+        Lookup::Action(action) => perform(app, action)?,
+        Lookup::Pending => {}
+        Lookup::Unbound => input(app, key),
+    }
+    Ok(())
+}
+
+/// Scroll the log pane if `key` moves around, returns whether it did.
+fn scroll_log(app: &mut App, key: KeyEvent) -> bool {
+    match app.keymap.press(CurrentMode::Main, key) {
+        Lookup::Action(Action::Previous) => app.log_scroll = app.log_scroll.saturating_add(1),
+        Lookup::Action(Action::Next) => app.log_scroll = app.log_scroll.saturating_sub(1),
+        // Cut down to the length of the log when drawn.
+        Lookup::Action(Action::First) => app.log_scroll = usize::MAX,
+        Lookup::Action(Action::Last) => app.log_scroll = 0,
+        Lookup::Pending => {}
+        _ => return false,
+    }
+    true
+}
+
+fn on_mouse(app: &mut App, mouse: MouseEvent) -> Result<(), AppError> {
+    let position = Position::new(mouse.column, mouse.row);
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            if app.overlay.take().is_some() {
+            } else if let Some(target) = app.regions.target_at(position) {
+                click(app, target)?;
+            } else if app.regions.on_split(position, app.split) {
+                app.resizing = true;
+            } else if app.regions.list.contains(position)
+                && matches!(app.current_mode, CurrentMode::Main | CurrentMode::Searching)
+            {
+                let row = usize::from(position.y - app.regions.list.y);
+                let index = app.list_state.offset() + row;
+                if index < app.visible_recipes().len() {
+                    app.list_state.select(Some(index));
+                    app.open_selected();
                 }
-                KeyCode::Char('n') | KeyCode::Char('q') => {
-                    app.should_quit = true;
+            }
+        }
+        MouseEventKind::Drag(MouseButton::Left) if app.resizing => {
+            app.resize_split(position.x);
+        }
+        MouseEventKind::Up(MouseButton::Left) => {
+            app.resizing = false;
+        }
+        MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+            let down = mouse.kind == MouseEventKind::ScrollDown;
+            if app.overlay == Some(Overlay::Log) {
+                app.log_scroll = if down {
+                    app.log_scroll.saturating_sub(1)
+                } else {
+                    app.log_scroll.saturating_add(1)
+                };
+            } else if app.regions.list.contains(position) {
+                if down {
+                    app.list_state.scroll_down_by(1);
+                } else {
+                    app.list_state.scroll_up_by(1);
                 }
-                _ => {}
-            },
-            CurrentMode::Editing if key.kind == KeyEventKind::Press => match key.code {
-                KeyCode::Enter => {
-                    if let Some(editing) = &app.currently_editing {
-                        match editing {
-                            CurrentlyEditing::Name => {
-                                app.currently_editing = Some(CurrentlyEditing::Description);
-                            }
-                            CurrentlyEditing::Description => {
-                                app.save_current_recipe();
-                                app.current_mode = CurrentMode::Main;
-                            }
-                            _ => todo!(),
-                        }
+            } else if app.regions.card.contains(position) {
+                app.description_scroll = if down {
+                    app.description_scroll.saturating_add(1)
+                } else {
+                    app.description_scroll.saturating_sub(1)
+                };
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn click(app: &mut App, target: Target) -> Result<(), AppError> {
+    match target {
+        Target::Action(action) => return perform(app, action),
+        Target::FilterField(field) => app.filter_field = field,
+        Target::Editing(editing) => app.currently_editing = Some(editing),
+        Target::PaletteEntry(i) => {
+            app.palette.list_state.select(Some(i));
+            return perform(app, Action::Confirm);
+        }
+    }
+    Ok(())
+}
+
+/// Do what `action` means in the current mode.
+fn perform(app: &mut App, action: Action) -> Result<(), AppError> {
+    app.access().check_action(action)?;
+    match action {
+        Action::Help => {
+            app.overlay = Some(Overlay::Help);
+            return Ok(());
+        }
+        Action::History => {
+            app.overlay = Some(Overlay::History);
+            return Ok(());
+        }
+        Action::Log => {
+            app.overlay = Some(Overlay::Log);
+            app.log_scroll = 0;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    match app.current_mode {
+        CurrentMode::Main => match action {
+            Action::NewRecipe => {
+                app.new_recipe()?;
+                app.current_mode = CurrentMode::Editing;
+                app.start_editing();
+            }
+            Action::Palette => {
+                app.open_palette(None);
+            }
+            Action::Theme
+            | Action::Export
+            | Action::AddGlass
+            | Action::RemoveGlass
+            | Action::ServeIn
+            | Action::ProductPhoto
+            | Action::SetCost
+            | Action::SetStock => {
+                app.open_palette(Some(action));
+            }
+            Action::LogDrink => {
+                app.log_drink()?;
+            }
+            Action::Collection => {
+                app.switch_collection();
+            }
+            Action::Edit => {
+                app.current_mode = CurrentMode::Editing;
+                app.start_editing();
+            }
+            Action::Undo => {
+                app.undo()?;
+            }
+            Action::Redo => {
+                app.redo()?;
+            }
+            Action::AttachPhoto => {
+                app.photo_product = None;
+                app.current_mode = CurrentMode::Editing;
+                app.currently_editing = Some(CurrentlyEditing::Photo);
+            }
+            Action::TogglePhoto => {
+                app.photo.visible = !app.photo.visible;
+            }
+            Action::Search => {
+                app.current_mode = CurrentMode::Searching;
+            }
+            Action::Filter => {
+                app.current_mode = CurrentMode::Filtering;
+                app.filter_field = FilterField::Glassware;
+            }
+            Action::Quit => {
+                app.current_mode = CurrentMode::Exiting;
+            }
+            Action::OpenInEditor => {
+                app.open_in_editor = true;
+            }
+            Action::Save => {
+                app.save_current_recipe()?;
+            }
+            Action::Delete => {
+                app.current_mode = CurrentMode::Deleting;
+            }
+            Action::Rename => {
+                app.current_mode = CurrentMode::Editing;
+                app.start_naming(CurrentlyEditing::Rename);
+            }
+            Action::Duplicate => {
+                app.current_mode = CurrentMode::Editing;
+                app.start_naming(CurrentlyEditing::Duplicate);
+            }
+            Action::Next => {
+                app.list_state.select_next();
+            }
+            Action::Previous => {
+                app.list_state.select_previous();
+            }
+            Action::First => {
+                app.list_state.select_first();
+            }
+            Action::Last => {
+                app.list_state.select_last();
+            }
+            Action::Deselect => {
+                app.list_state.select(None);
+            }
+            Action::Open => {
+                app.open_selected();
+            }
+            _ => {}
+        },
+        CurrentMode::Searching => match action {
+            Action::Cancel => {
+                app.clear_search();
+                app.current_mode = CurrentMode::Main;
+            }
+            Action::Confirm => {
+                app.current_mode = CurrentMode::Main;
+            }
+            Action::Next => {
+                app.list_state.select_next();
+            }
+            Action::Previous => {
+                app.list_state.select_previous();
+            }
+            _ => {}
+        },
+        CurrentMode::Filtering => match action {
+            Action::Cancel => {
+                app.current_mode = CurrentMode::Main;
+            }
+            Action::Confirm => {
+                app.apply_filter();
+                app.current_mode = CurrentMode::Main;
+            }
+            Action::NextField => {
+                app.filter_field = app.filter_field.next();
+            }
+            _ => {}
+        },
+        CurrentMode::Palette => match action {
+            Action::Cancel => {
+                app.current_mode = CurrentMode::Main;
+            }
+            Action::Confirm => {
+                let Some(entry) = app.palette.selected(&app.keymap, app.access()) else {
+                    return Ok(());
+                };
+                app.current_mode = CurrentMode::Main;
+                match entry.argument {
+                    Some(argument) => perform_with(app, entry.action, &argument)?,
+                    None if entry.action.argument().is_some() => {
+                        app.open_palette(Some(entry.action));
                     }
+                    None => perform(app, entry.action)?,
                 }
-                KeyCode::Esc => {
-                    app.current_mode = CurrentMode::Main;
-                    app.currently_editing = None;
-                }
-                KeyCode::Tab => {
-                    app.toggle_editing();
-                }
-                _ => {
-                    if let Some(editing) = &app.currently_editing {
-                        match editing {
-                            CurrentlyEditing::Name => {
-                                app.name_text.input(key);
-                            }
-                            CurrentlyEditing::Description => {
-                                app.desc_text.input(key);
-                            }
-                            _ => todo!(),
+            }
+            Action::Next | Action::Previous => {
+                let count = app.palette.entries(&app.keymap, app.access()).len();
+                let i = app.palette.list_state.selected().unwrap_or(0);
+                let i = if action == Action::Next {
+                    (i + 1).min(count.saturating_sub(1))
+                } else {
+                    i.saturating_sub(1)
+                };
+                app.palette.list_state.select(Some(i));
+            }
+            _ => {}
+        },
+        CurrentMode::Deleting => match action {
+            Action::Confirm => {
+                app.current_mode = CurrentMode::Main;
+                app.delete_current_recipe()?;
+            }
+            Action::Cancel => {
+                app.current_mode = CurrentMode::Main;
+            }
+            _ => {}
+        },
+        CurrentMode::Exiting => match action {
+            Action::Quit => {
+                app.should_quit = true;
+            }
+            Action::Cancel => {
+                app.current_mode = CurrentMode::Main;
+            }
+            _ => {}
+        },
+        CurrentMode::Editing => match action {
+            Action::Confirm => {
+                if let Some(editing) = &app.currently_editing {
+                    match editing {
+                        CurrentlyEditing::Name => {
+                            app.currently_editing = Some(CurrentlyEditing::Description);
+                        }
+                        CurrentlyEditing::Description => {
+                            app.current_mode = CurrentMode::Main;
+                            let committed = app.commit_edit();
+                            app.currently_editing = None;
+                            committed?;
+                        }
+                        CurrentlyEditing::Photo => {
+                            app.current_mode = CurrentMode::Main;
+                            let attached = app.attach_photo();
+                            app.currently_editing = None;
+                            attached?;
+                        }
+                        // A name that is taken keeps the prompt open.
+                        CurrentlyEditing::Rename => {
+                            app.rename_current_recipe()?;
+                            app.current_mode = CurrentMode::Main;
+                        }
+                        CurrentlyEditing::Duplicate => {
+                            app.duplicate_current_recipe()?;
+                            app.current_mode = CurrentMode::Main;
                         }
                     }
                 }
-            },
+            }
+            Action::Cancel => {
+                app.current_mode = CurrentMode::Main;
+                app.currently_editing = None;
+                app.photo_product = None;
+            }
+            Action::NextField => {
+                app.toggle_editing();
+            }
             _ => {}
-        }
+        },
     }
     Ok(())
 }
+
+/// Do an action that needs an argument, picked in the palette.
+fn perform_with(app: &mut App, action: Action, argument: &str) -> Result<(), AppError> {
+    app.access().check_action(action)?;
+    match action {
+        Action::Theme => app.change_theme(argument),
+        Action::Export => app.export(Path::new(argument)),
+        Action::AddGlass => app.add_glass(argument),
+        Action::RemoveGlass => app.remove_glass(argument),
+        Action::ServeIn => app.serve_in(argument),
+        Action::ProductPhoto => app.choose_photo_product(argument),
+        Action::SetCost => app.set_cost(argument),
+        Action::SetStock => app.set_stock(argument),
+        _ => perform(app, action),
+    }
+}
+
+/// Pasted text goes to whatever text field has focus, as if typed in, but
+/// without running any actions. Only the description takes line breaks.
+fn paste(app: &mut App, text: &str) {
+    let multiline = app.current_mode == CurrentMode::Editing
+        && app.currently_editing == Some(CurrentlyEditing::Description);
+    for c in text.chars() {
+        let code = match c {
+            '\n' if multiline => KeyCode::Enter,
+            '\r' | '\n' => continue,
+            c => KeyCode::Char(c),
+        };
+        input(app, KeyEvent::from(code));
+    }
+}
+
+/// Keys that aren't bound to anything go to whatever text field has focus.
+fn input(app: &mut App, key: KeyEvent) {
+    match app.current_mode {
+        CurrentMode::Searching => {
+            if app.search_text.input(key) {
+                app.list_state.select_first();
+            }
+        }
+        CurrentMode::Filtering => {
+            let field = app.filter_field;
+            app.filter_text(field).input(key);
+        }
+        CurrentMode::Palette => {
+            if app.palette.text.input(key) {
+                app.palette.list_state.select(Some(0));
+            }
+        }
+        CurrentMode::Editing => {
+            if let Some(editing) = &app.currently_editing {
+                match editing {
+                    CurrentlyEditing::Name => {
+                        app.name_text.input(key);
+                    }
+                    CurrentlyEditing::Description => {
+                        app.desc_text.input(key);
+                    }
+                    CurrentlyEditing::Photo => {
+                        app.photo_text.input(key);
+                    }
+                    CurrentlyEditing::Rename | CurrentlyEditing::Duplicate => {
+                        app.new_name_text.input(key);
+                    }
+                }
+            }
+        }
+        CurrentMode::Main | CurrentMode::Deleting | CurrentMode::Exiting => {}
+    }
+}
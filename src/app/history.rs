@@ -0,0 +1,396 @@
+//! Undo and redo of edits, for the whole session.
+
+use crate::{
+    app::error::AppError,
+    sys::{
+        data::{DumbRecipe, Reposotory},
+        glass::HouseGlass,
+        recipe::{Product, Recipe},
+    },
+};
+
+/// A single reversible change, holding what was there before and after.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// The recipe being edited.
+    Current {
+        before: DumbRecipe,
+        after: DumbRecipe,
+    },
+    /// A recipe in the repository, `None` when it doesn't exist.
+    Recipe {
+        name: String,
+        before: Option<Recipe>,
+        after: Option<Recipe>,
+    },
+    /// A product in the repository, `None` when it doesn't exist.
+    Product {
+        name: String,
+        before: Option<Product>,
+        after: Option<Product>,
+    },
+    /// A house glass, `None` when the bar doesn't have it.
+    Glass {
+        name: String,
+        before: Option<HouseGlass>,
+        after: Option<HouseGlass>,
+    },
+    /// How many drinks of a recipe were poured.
+    Poured {
+        recipe: String,
+        before: u32,
+        after: u32,
+    },
+}
+
+impl Change {
+    /// Change `name` in the repository to `after`.
+    pub fn recipe(repo: &Reposotory, name: &str, after: Option<Recipe>) -> Change {
+        Change::Recipe {
+            name: name.to_string(),
+            before: repo.recipes.get(name).cloned(),
+            after,
+        }
+    }
+
+    pub fn product(repo: &Reposotory, name: &str, after: Option<Product>) -> Change {
+        Change::Product {
+            name: name.to_string(),
+            before: repo.ingredients.get(name).cloned(),
+            after,
+        }
+    }
+
+    pub fn glass(repo: &Reposotory, name: &str, after: Option<HouseGlass>) -> Change {
+        Change::Glass {
+            name: name.to_string(),
+            before: repo.glasses.get(name).cloned(),
+            after,
+        }
+    }
+
+    /// Log `after` drinks of `recipe` as poured in all.
+    pub fn poured(repo: &Reposotory, recipe: &str, after: u32) -> Change {
+        Change::Poured {
+            recipe: recipe.to_string(),
+            before: repo.poured.get(recipe).copied().unwrap_or(0),
+            after,
+        }
+    }
+
+    fn inverse(&self) -> Change {
+        match self.clone() {
+            Change::Current { before, after } => Change::Current {
+                before: after,
+                after: before,
+            },
+            Change::Recipe {
+                name,
+                before,
+                after,
+            } => Change::Recipe {
+                name,
+                before: after,
+                after: before,
+            },
+            Change::Product {
+                name,
+                before,
+                after,
+            } => Change::Product {
+                name,
+                before: after,
+                after: before,
+            },
+            Change::Glass {
+                name,
+                before,
+                after,
+            } => Change::Glass {
+                name,
+                before: after,
+                after: before,
+            },
+            Change::Poured {
+                recipe,
+                before,
+                after,
+            } => Change::Poured {
+                recipe,
+                before: after,
+                after: before,
+            },
+        }
+    }
+
+    /// Whether `repo` still has what this change expects to replace. It
+    /// won't when someone else changed it in the meantime.
+    fn applies_to(&self, repo: &Reposotory) -> Result<(), AppError> {
+        let (name, unchanged) = match self {
+            Change::Current { .. } => return Ok(()),
+            Change::Recipe { name, before, .. } => {
+                (name, repo.recipes.get(name) == before.as_ref())
+            }
+            Change::Product { name, before, .. } => {
+                (name, repo.ingredients.get(name) == before.as_ref())
+            }
+            Change::Glass { name, before, .. } => (name, repo.glasses.get(name) == before.as_ref()),
+            Change::Poured { recipe, before, .. } => (
+                recipe,
+                repo.poured.get(recipe).copied().unwrap_or(0) == *before,
+            ),
+        };
+        if unchanged {
+            Ok(())
+        } else {
+            Err(AppError::Conflict(name.clone()))
+        }
+    }
+
+    fn apply(&self, repo: &mut Reposotory, current: &mut DumbRecipe) {
+        match self {
+            Change::Current { after, .. } => *current = after.clone(),
+            _ => self.apply_to(repo),
+        }
+    }
+
+    /// Make the change to `repo`, leaving out the recipe being edited.
+    fn apply_to(&self, repo: &mut Reposotory) {
+        match self {
+            Change::Current { .. } => {}
+            Change::Recipe { name, after, .. } => match after {
+                Some(recipe) => {
+                    repo.recipes.insert(name.clone(), recipe.clone());
+                }
+                None => {
+                    repo.recipes.remove(name);
+                }
+            },
+            Change::Product { name, after, .. } => match after {
+                Some(product) => {
+                    repo.ingredients.insert(name.clone(), product.clone());
+                }
+                None => {
+                    repo.ingredients.remove(name);
+                }
+            },
+            Change::Glass { name, after, .. } => match after {
+                Some(glass) => {
+                    repo.glasses.insert(name.clone(), glass.clone());
+                }
+                None => {
+                    repo.glasses.remove(name);
+                }
+            },
+            Change::Poured { recipe, after, .. } => {
+                if *after == 0 {
+                    repo.poured.remove(recipe);
+                } else {
+                    repo.poured.insert(recipe.clone(), *after);
+                }
+            }
+        }
+    }
+
+    /// What changed, one line per field, like `name: Daiquiri → Mojito`.
+    pub fn details(&self) -> Vec<String> {
+        match self {
+            Change::Current { before, after } => diff(Some(before), Some(after)),
+            Change::Recipe {
+                name,
+                before,
+                after,
+            } => match (before, after) {
+                (None, Some(_)) => vec![format!("added recipe {name}")],
+                (Some(_), None) => vec![format!("removed recipe {name}")],
+                (before, after) => {
+                    let before = before.clone().map(Recipe::dumb);
+                    let after = after.clone().map(Recipe::dumb);
+                    diff(before.as_ref(), after.as_ref())
+                }
+            },
+            Change::Product {
+                name,
+                before,
+                after,
+            } => match (before, after) {
+                (None, Some(_)) => vec![format!("added product {name}")],
+                (Some(_), None) => vec![format!("removed product {name}")],
+                _ => vec![format!("changed product {name}")],
+            },
+            Change::Glass {
+                name,
+                before,
+                after,
+            } => match (before, after) {
+                (None, Some(_)) => vec![format!("added glass {name}")],
+                (Some(_), None) => vec![format!("removed glass {name}")],
+                _ => vec![format!("changed glass {name}")],
+            },
+            Change::Poured {
+                recipe,
+                before,
+                after,
+            } => vec![format!("{recipe} poured: {before} → {after}")],
+        }
+    }
+}
+
+/// Fields that differ between two versions of a recipe.
+fn diff(before: Option<&DumbRecipe>, after: Option<&DumbRecipe>) -> Vec<String> {
+    let (Some(before), Some(after)) = (before, after) else {
+        return Vec::new();
+    };
+    let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    let debug = |value: &dyn std::fmt::Debug| format!("{value:?}");
+    let ingredients = |recipe: &DumbRecipe| {
+        let lines: Vec<_> = recipe
+            .ingredients
+            .iter()
+            .map(|(milis, name)| format!("{milis} {name}"))
+            .collect();
+        lines.join(", ")
+    };
+
+    let mut lines = Vec::new();
+    let mut changed = |field: &str, before: String, after: String| {
+        if before != after {
+            lines.push(format!("{field}: {before} → {after}"));
+        }
+    };
+    changed("name", before.name.clone(), after.name.clone());
+    changed(
+        "short description",
+        text(&before.short_desc),
+        text(&after.short_desc),
+    );
+    changed("ingredients", ingredients(before), ingredients(after));
+    changed(
+        "dilution",
+        before.dilution.to_string(),
+        after.dilution.to_string(),
+    );
+    changed(
+        "glassware",
+        debug(&before.glassware),
+        debug(&after.glassware),
+    );
+    changed("glass", text(&before.glass), text(&after.glass));
+    changed(
+        "layered",
+        before.layered.to_string(),
+        after.layered.to_string(),
+    );
+    changed(
+        "on the rocks",
+        before.on_the_rocks.to_string(),
+        after.on_the_rocks.to_string(),
+    );
+    changed("photo", text(&before.photo), text(&after.photo));
+    changed("family", debug(&before.family), debug(&after.family));
+    // Descriptions are long, only say that they changed.
+    if before.description != after.description {
+        lines.push("description changed".to_string());
+    }
+    lines
+}
+
+/// Changes made together and undone together.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub description: String,
+    pub changes: Vec<Change>,
+}
+
+impl Step {
+    /// The step undoing this one.
+    pub fn reverted(&self) -> Step {
+        Step {
+            description: self.description.clone(),
+            changes: self.changes.iter().rev().map(Change::inverse).collect(),
+        }
+    }
+
+    /// Whether the step changes the repository, and not only the recipe
+    /// being edited.
+    pub fn changes_repo(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| !matches!(change, Change::Current { .. }))
+    }
+
+    /// Fails when something this step changes was changed since it was
+    /// made.
+    pub fn applies_to(&self, repo: &Reposotory) -> Result<(), AppError> {
+        self.changes
+            .iter()
+            .try_for_each(|change| change.applies_to(repo))
+    }
+
+    /// Make the changes to `repo` without keeping a history, for those
+    /// who change it without a recipe open, like over sftp.
+    pub fn apply_to(&self, repo: &mut Reposotory) {
+        for change in &self.changes {
+            change.apply_to(repo);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    done: Vec<Step>,
+    undone: Vec<Step>,
+}
+
+impl History {
+    /// Apply `step` and remember it, forgetting anything undone.
+    pub fn apply(&mut self, step: Step, repo: &mut Reposotory, current: &mut DumbRecipe) {
+        for change in &step.changes {
+            change.apply(repo, current);
+        }
+        self.done.push(step);
+        self.undone.clear();
+    }
+
+    /// Revert the last step, returning its description.
+    pub fn undo(&mut self, repo: &mut Reposotory, current: &mut DumbRecipe) -> Option<String> {
+        let step = self.done.pop()?;
+        for change in &step.reverted().changes {
+            change.apply(repo, current);
+        }
+        let description = step.description.clone();
+        self.undone.push(step);
+        Some(description)
+    }
+
+    /// Apply the last undone step again, returning its description.
+    pub fn redo(&mut self, repo: &mut Reposotory, current: &mut DumbRecipe) -> Option<String> {
+        let step = self.undone.pop()?;
+        for change in &step.changes {
+            change.apply(repo, current);
+        }
+        let description = step.description.clone();
+        self.done.push(step);
+        Some(description)
+    }
+
+    /// The step [`History::undo`] would revert, as it would be applied.
+    pub fn next_undo(&self) -> Option<Step> {
+        self.done.last().map(Step::reverted)
+    }
+
+    /// The step [`History::redo`] would apply again.
+    pub fn next_redo(&self) -> Option<&Step> {
+        self.undone.last()
+    }
+
+    /// Steps that can be undone, oldest first.
+    pub fn done(&self) -> &[Step] {
+        &self.done
+    }
+
+    /// Steps that can be redone, the next one last.
+    pub fn undone(&self) -> &[Step] {
+        &self.undone
+    }
+}
@@ -0,0 +1,720 @@
+//! Key bindings, from keys (or chords of them) to actions, per mode.
+//!
+//! The bindings start from a preset and can be overridden in `keymap.toml`
+//! in the config directory:
+//!
+//! ```toml
+//! preset = "vim"
+//!
+//! [main]
+//! "ctrl-x ctrl-c" = "quit"
+//! "p" = "unbound"
+//! ```
+
+use std::{collections::BTreeMap, fmt, fs, path::PathBuf, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use eyre::{eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{app::CurrentMode, sys::project_dirs};
+
+/// Something the user can ask for with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    NewRecipe,
+    Edit,
+    Save,
+    Delete,
+    Rename,
+    Duplicate,
+    AttachPhoto,
+    TogglePhoto,
+    Search,
+    Filter,
+    OpenInEditor,
+    Next,
+    Previous,
+    First,
+    Last,
+    Open,
+    Deselect,
+    NextField,
+    Confirm,
+    Cancel,
+    Help,
+    Undo,
+    Redo,
+    History,
+    Log,
+    Palette,
+    Theme,
+    Export,
+    Collection,
+    AddGlass,
+    RemoveGlass,
+    ServeIn,
+    ProductPhoto,
+    LogDrink,
+    SetCost,
+    SetStock,
+}
+
+impl Action {
+    pub const ALL: [Action; 37] = [
+        Action::Quit,
+        Action::NewRecipe,
+        Action::Edit,
+        Action::Save,
+        Action::Delete,
+        Action::Rename,
+        Action::Duplicate,
+        Action::AttachPhoto,
+        Action::TogglePhoto,
+        Action::Search,
+        Action::Filter,
+        Action::OpenInEditor,
+        Action::Next,
+        Action::Previous,
+        Action::First,
+        Action::Last,
+        Action::Open,
+        Action::Deselect,
+        Action::NextField,
+        Action::Confirm,
+        Action::Cancel,
+        Action::Help,
+        Action::Undo,
+        Action::Redo,
+        Action::History,
+        Action::Log,
+        Action::Palette,
+        Action::Theme,
+        Action::Export,
+        Action::Collection,
+        Action::AddGlass,
+        Action::RemoveGlass,
+        Action::ServeIn,
+        Action::ProductPhoto,
+        Action::LogDrink,
+        Action::SetCost,
+        Action::SetStock,
+    ];
+
+    /// The name used in the keymap file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NewRecipe => "new-recipe",
+            Action::Edit => "edit",
+            Action::Save => "save",
+            Action::Delete => "delete",
+            Action::Rename => "rename",
+            Action::Duplicate => "duplicate",
+            Action::AttachPhoto => "attach-photo",
+            Action::TogglePhoto => "toggle-photo",
+            Action::Search => "search",
+            Action::Filter => "filter",
+            Action::OpenInEditor => "open-in-editor",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Open => "open",
+            Action::Deselect => "deselect",
+            Action::NextField => "next-field",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+            Action::Help => "help",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::History => "history",
+            Action::Log => "log",
+            Action::Palette => "palette",
+            Action::Theme => "theme",
+            Action::Export => "export",
+            Action::Collection => "switch-collection",
+            Action::AddGlass => "add-glass",
+            Action::RemoveGlass => "remove-glass",
+            Action::ServeIn => "serve-in",
+            Action::ProductPhoto => "product-photo",
+            Action::LogDrink => "log-drink",
+            Action::SetCost => "set-cost",
+            Action::SetStock => "set-stock",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NewRecipe => "new recipe",
+            Action::Edit => "edit recipe",
+            Action::Save => "save recipe",
+            Action::Delete => "delete recipe",
+            Action::Rename => "rename recipe",
+            Action::Duplicate => "duplicate as variation",
+            Action::AttachPhoto => "attach photo",
+            Action::TogglePhoto => "toggle photo",
+            Action::Search => "search",
+            Action::Filter => "filter",
+            Action::OpenInEditor => "edit in $EDITOR",
+            Action::Next => "move down",
+            Action::Previous => "move up",
+            Action::First => "jump to top",
+            Action::Last => "jump to bottom",
+            Action::Open => "open recipe",
+            Action::Deselect => "clear selection",
+            Action::NextField => "next field",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+            Action::Help => "help",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::History => "show history",
+            Action::Log => "show log",
+            Action::Palette => "command palette",
+            Action::Theme => "change theme",
+            Action::Export => "export recipes",
+            Action::Collection => "switch collection",
+            Action::AddGlass => "add or change a house glass",
+            Action::RemoveGlass => "remove a house glass",
+            Action::ServeIn => "serve in a house glass",
+            Action::ProductPhoto => "attach photo to a product",
+            Action::LogDrink => "log a drink as poured",
+            Action::SetCost => "set what a product costs",
+            Action::SetStock => "count the stock of a product",
+        }
+    }
+
+    /// Whether the action reads or writes a file named by the user, on the
+    /// machine the app runs on.
+    pub fn uses_files(&self) -> bool {
+        matches!(
+            self,
+            Action::Export | Action::AttachPhoto | Action::ProductPhoto
+        )
+    }
+
+    /// What to ask for before doing the action, for those that need it.
+    pub fn argument(&self) -> Option<&'static str> {
+        match self {
+            Action::Theme => Some("theme"),
+            Action::Export => Some("file"),
+            Action::AddGlass => Some("name, glassware, ml"),
+            Action::RemoveGlass | Action::ServeIn => Some("glass"),
+            Action::ProductPhoto => Some("product"),
+            Action::SetCost => Some("product, price a litre"),
+            Action::SetStock => Some("product, ml"),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| format!("Unknown action {s:?}"))
+    }
+}
+
+/// A single key press, with the modifiers held down.
+///
+/// Shift is folded into the character, so `G` is written as `G` and not
+/// `shift-g`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(event.code, KeyCode::Char(_)) {
+            modifiers |= event.modifiers & KeyModifiers::SHIFT;
+        }
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 16] = [
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("space", KeyCode::Char(' ')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            f.write_str("shift-")?;
+        }
+        if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, code)| *code == self.code) {
+            return f.write_str(name);
+        }
+        match self.code {
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let (modifier, tail) = match rest.split_once('-') {
+                Some((modifier, tail)) if !tail.is_empty() => (modifier, tail),
+                _ => break,
+            };
+            modifiers |= match modifier {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier {modifier:?} in {s:?}")),
+            };
+            rest = tail;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let named = NAMED_KEYS.iter().find(|(name, _)| *name == rest);
+                match named {
+                    Some((_, code)) => *code,
+                    None => match rest.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(n) => KeyCode::F(n),
+                        None => return Err(format!("Unknown key {rest:?} in {s:?}")),
+                    },
+                }
+            }
+        };
+
+        // Written as `shift-g` or `G`, crossterm reports it as `G`.
+        if let KeyCode::Char(c) = code {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                modifiers -= KeyModifiers::SHIFT;
+                return Ok(Key {
+                    code: KeyCode::Char(c.to_ascii_uppercase()),
+                    modifiers,
+                });
+            }
+        }
+        Ok(Key { code, modifiers })
+    }
+}
+
+/// One or more keys pressed one after the other, like `ctrl-x ctrl-c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord(pub Vec<Key>);
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<_> = self.0.iter().map(Key::to_string).collect();
+        f.write_str(&keys.join(" "))
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split_whitespace()
+            .map(Key::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err("Empty key binding".to_string());
+        }
+        Ok(Chord(keys))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl Preset {
+    fn bindings(&self, mode: CurrentMode) -> &'static [(&'static str, Action)] {
+        use Action::*;
+        match (self, mode) {
+            (Preset::Default, CurrentMode::Main) => &[
+                ("q", Quit),
+                ("n", NewRecipe),
+                ("e", Edit),
+                ("s", Save),
+                ("d", Delete),
+                ("r", Rename),
+                ("c", Duplicate),
+                ("/", Search),
+                ("f", Filter),
+                ("a", AttachPhoto),
+                ("i", TogglePhoto),
+                ("v", OpenInEditor),
+                ("p", LogDrink),
+                ("j", Next),
+                ("down", Next),
+                ("k", Previous),
+                ("up", Previous),
+                ("enter", Open),
+                ("esc", Deselect),
+                ("u", Undo),
+                ("ctrl-r", Redo),
+                ("H", History),
+                ("L", Log),
+                (":", Palette),
+                ("ctrl-p", Palette),
+                ("T", Theme),
+                ("C", Collection),
+                ("?", Help),
+                ("f1", Help),
+            ],
+            (Preset::Vim, CurrentMode::Main) => &[
+                ("q", Quit),
+                ("Z Z", Quit),
+                ("O", NewRecipe),
+                ("o", Edit),
+                ("w", Save),
+                ("d d", Delete),
+                ("c w", Rename),
+                ("y y", Duplicate),
+                ("/", Search),
+                ("f", Filter),
+                ("a", AttachPhoto),
+                ("i", TogglePhoto),
+                ("v", OpenInEditor),
+                ("p", LogDrink),
+                ("j", Next),
+                ("down", Next),
+                ("k", Previous),
+                ("up", Previous),
+                ("g g", First),
+                ("G", Last),
+                ("enter", Open),
+                ("l", Open),
+                ("esc", Deselect),
+                ("u", Undo),
+                ("ctrl-r", Redo),
+                ("H", History),
+                ("L", Log),
+                (":", Palette),
+                ("ctrl-p", Palette),
+                ("T", Theme),
+                ("g t", Collection),
+                ("?", Help),
+                ("f1", Help),
+            ],
+            (Preset::Emacs, CurrentMode::Main) => &[
+                ("ctrl-x ctrl-c", Quit),
+                ("ctrl-x n", NewRecipe),
+                ("ctrl-x ctrl-f", Edit),
+                ("ctrl-x ctrl-s", Save),
+                ("ctrl-x k", Delete),
+                ("ctrl-x ctrl-w", Rename),
+                ("alt-w", Duplicate),
+                ("ctrl-s", Search),
+                ("ctrl-x f", Filter),
+                ("ctrl-x i", AttachPhoto),
+                ("ctrl-x p", TogglePhoto),
+                ("ctrl-x ctrl-e", OpenInEditor),
+                ("ctrl-x d", LogDrink),
+                ("ctrl-n", Next),
+                ("down", Next),
+                ("ctrl-p", Previous),
+                ("up", Previous),
+                ("alt-<", First),
+                ("alt->", Last),
+                ("enter", Open),
+                ("ctrl-g", Deselect),
+                ("ctrl-x u", Undo),
+                ("ctrl-x r", Redo),
+                ("ctrl-x h", History),
+                ("ctrl-x l", Log),
+                ("alt-x", Palette),
+                ("ctrl-x t", Theme),
+                ("ctrl-x b", Collection),
+                ("ctrl-h", Help),
+                ("f1", Help),
+            ],
+            (_, CurrentMode::Searching) => match self {
+                Preset::Emacs => &[
+                    ("ctrl-g", Cancel),
+                    ("enter", Confirm),
+                    ("ctrl-n", Next),
+                    ("down", Next),
+                    ("ctrl-p", Previous),
+                    ("up", Previous),
+                    ("f1", Help),
+                ],
+                _ => &[
+                    ("esc", Cancel),
+                    ("enter", Confirm),
+                    ("down", Next),
+                    ("up", Previous),
+                    ("f1", Help),
+                ],
+            },
+            (_, CurrentMode::Filtering | CurrentMode::Editing) => match self {
+                Preset::Emacs => &[
+                    ("ctrl-g", Cancel),
+                    ("enter", Confirm),
+                    ("tab", NextField),
+                    ("f1", Help),
+                ],
+                _ => &[
+                    ("esc", Cancel),
+                    ("enter", Confirm),
+                    ("tab", NextField),
+                    ("f1", Help),
+                ],
+            },
+            (_, CurrentMode::Palette) => match self {
+                Preset::Emacs => &[
+                    ("ctrl-g", Cancel),
+                    ("enter", Confirm),
+                    ("ctrl-n", Next),
+                    ("down", Next),
+                    ("ctrl-p", Previous),
+                    ("up", Previous),
+                    ("f1", Help),
+                ],
+                _ => &[
+                    ("esc", Cancel),
+                    ("enter", Confirm),
+                    ("ctrl-n", Next),
+                    ("down", Next),
+                    ("ctrl-p", Previous),
+                    ("up", Previous),
+                    ("f1", Help),
+                ],
+            },
+            (_, CurrentMode::Deleting) => match self {
+                Preset::Emacs => &[
+                    ("y", Confirm),
+                    ("n", Cancel),
+                    ("ctrl-g", Cancel),
+                    ("f1", Help),
+                ],
+                _ => &[("y", Confirm), ("n", Cancel), ("esc", Cancel), ("f1", Help)],
+            },
+            (_, CurrentMode::Exiting) => match self {
+                Preset::Emacs => &[
+                    ("y", Quit),
+                    ("q", Quit),
+                    ("n", Cancel),
+                    ("ctrl-g", Cancel),
+                    ("?", Help),
+                    ("f1", Help),
+                ],
+                _ => &[
+                    ("y", Quit),
+                    ("q", Quit),
+                    ("n", Cancel),
+                    ("esc", Cancel),
+                    ("?", Help),
+                    ("f1", Help),
+                ],
+            },
+        }
+    }
+}
+
+/// The keymap file, a preset and per mode overrides.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeymapConfig {
+    #[serde(default)]
+    pub preset: Preset,
+    /// Chord to action name, or to `unbound` to drop a binding.
+    #[serde(flatten)]
+    pub modes: BTreeMap<CurrentMode, BTreeMap<String, String>>,
+}
+
+/// What a key press amounted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    /// The start of a chord, waiting for more keys.
+    Pending,
+    /// Not bound, so text fields get to see it.
+    Unbound,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: BTreeMap<CurrentMode, Vec<(Chord, Action)>>,
+    /// Keys of a chord typed so far.
+    pending: Vec<Key>,
+}
+
+impl Keymap {
+    pub fn preset(preset: Preset) -> Keymap {
+        let bindings = CurrentMode::ALL
+            .into_iter()
+            .map(|mode| {
+                let bindings = preset
+                    .bindings(mode)
+                    .iter()
+                    .map(|(chord, action)| {
+                        (chord.parse().expect("preset bindings are valid"), *action)
+                    })
+                    .collect();
+                (mode, bindings)
+            })
+            .collect();
+        Keymap {
+            bindings,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Some(project_dirs()?.config_dir().join("keymap.toml"))
+    }
+
+    /// Load the keymap file, or the default keymap if there is none.
+    pub fn load() -> Result<Keymap> {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Ok(Keymap::default());
+        };
+        let config: KeymapConfig = toml::from_str(&fs::read_to_string(&path)?)
+            .wrap_err_with(|| format!("Could not read {path:?}"))?;
+        Keymap::from_config(&config)
+    }
+
+    pub fn from_config(config: &KeymapConfig) -> Result<Keymap> {
+        let mut keymap = Keymap::preset(config.preset);
+        for (mode, overrides) in &config.modes {
+            for (text, action) in overrides {
+                let context = |err| eyre!("{err} in the {mode:?} binding {text:?}");
+                let chord: Chord = text.parse().map_err(context)?;
+                let action = match action.as_str() {
+                    "unbound" => None,
+                    action => Some(action.parse().map_err(context)?),
+                };
+                keymap.bind(*mode, chord, action);
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Bind `chord` to `action`, replacing what it was bound to, or unbind it.
+    pub fn bind(&mut self, mode: CurrentMode, chord: Chord, action: Option<Action>) {
+        let bindings = self.bindings.entry(mode).or_default();
+        bindings.retain(|(bound, _)| *bound != chord);
+        if let Some(action) = action {
+            bindings.push((chord, action));
+        }
+    }
+
+    pub fn bindings(&self, mode: CurrentMode) -> &[(Chord, Action)] {
+        self.bindings.get(&mode).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The keys of a chord typed so far.
+    pub fn pending(&self) -> &[Key] {
+        &self.pending
+    }
+
+    /// Feed a key press through the keymap.
+    pub fn press(&mut self, mode: CurrentMode, key: KeyEvent) -> Lookup {
+        self.pending.push(Key::from(key));
+
+        let bindings = self.bindings(mode);
+        if let Some((_, action)) = bindings.iter().find(|(chord, _)| chord.0 == self.pending) {
+            let action = *action;
+            self.pending.clear();
+            return Lookup::Action(action);
+        }
+        if bindings
+            .iter()
+            .any(|(chord, _)| chord.0.starts_with(&self.pending))
+        {
+            return Lookup::Pending;
+        }
+
+        // A chord that went nowhere, the last key might still mean something
+        // on its own.
+        let stray = self.pending.len() > 1;
+        self.pending.clear();
+        if stray {
+            self.press(mode, key)
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// The chords bound to each action in `mode`, in the order of
+    /// [`Action::ALL`].
+    pub fn actions(&self, mode: CurrentMode) -> Vec<(Action, Vec<&Chord>)> {
+        let bindings = self.bindings(mode);
+        Action::ALL
+            .into_iter()
+            .filter_map(|action| {
+                let chords: Vec<_> = bindings
+                    .iter()
+                    .filter(|(_, bound)| *bound == action)
+                    .map(|(chord, _)| chord)
+                    .collect();
+                (!chords.is_empty()).then_some((action, chords))
+            })
+            .collect()
+    }
+
+    /// Key hints for the footer, like `(j/down) move down`, one per action.
+    ///
+    /// Help comes first, the footer is narrow and it leads to the rest.
+    pub fn hints(&self, mode: CurrentMode) -> Vec<(Action, String)> {
+        let mut actions = self.actions(mode);
+        actions.sort_by_key(|(action, _)| *action != Action::Help);
+        actions
+            .into_iter()
+            .map(|(action, chords)| {
+                let chords: Vec<_> = chords.iter().map(ToString::to_string).collect();
+                let hint = format!("({}) {}", chords.join("/"), action.description());
+                (action, hint)
+            })
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset(Preset::Default)
+    }
+}
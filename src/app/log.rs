@@ -0,0 +1,177 @@
+//! Telling the user what happened: short lived toasts, and a log of
+//! everything traced while the app runs.
+//!
+//! The log is filled by [`LogLayer`], so anything logged with `tracing`
+//! shows up in the log pane, and warnings and errors also pop up as toasts.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use tokio::sync::Notify;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// Something that was logged.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// A message shown on top of everything for a little while.
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub level: Level,
+    pub message: String,
+    shown: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    records: VecDeque<Record>,
+    toasts: Vec<Toast>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    inner: Mutex<Inner>,
+    /// Woken when a toast pops up.
+    toasted: Notify,
+}
+
+/// The log and the toasts, shared with the [`LogLayer`] feeding them.
+#[derive(Debug, Clone, Default)]
+pub struct Log(Arc<Shared>);
+
+impl Log {
+    /// Records kept before the oldest are dropped.
+    const CAPACITY: usize = 1000;
+    const TOASTS: usize = 4;
+    pub const TOAST_TIME: Duration = Duration::from_secs(4);
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // A panic while holding the lock leaves nothing half written.
+        self.0
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// A layer sending everything at `INFO` and above here.
+    pub fn layer(&self) -> LogLayer {
+        LogLayer { log: self.clone() }
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        self.lock().records.iter().cloned().collect()
+    }
+
+    pub fn push(&self, record: Record) {
+        let mut inner = self.lock();
+        if inner.records.len() == Self::CAPACITY {
+            inner.records.pop_front();
+        }
+        inner.records.push_back(record);
+    }
+
+    /// Pop up `message`, only the latest few are kept.
+    pub fn toast(&self, level: Level, message: impl Into<String>) {
+        let mut inner = self.lock();
+        if inner.toasts.len() == Self::TOASTS {
+            inner.toasts.remove(0);
+        }
+        inner.toasts.push(Toast {
+            level,
+            message: message.into(),
+            shown: Instant::now(),
+        });
+        drop(inner);
+        self.0.toasted.notify_one();
+    }
+
+    /// Wait for the next toast, or return at once if one popped up since
+    /// the last wait.
+    pub async fn toasted(&self) {
+        self.0.toasted.notified().await;
+    }
+
+    /// When the next of the toasts still showing goes away, so whatever
+    /// shows them can redraw then.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.toasts()
+            .iter()
+            .map(|toast| toast.shown + Self::TOAST_TIME)
+            .min()
+    }
+
+    /// The toasts still showing, oldest first.
+    pub fn toasts(&self) -> Vec<Toast> {
+        let mut inner = self.lock();
+        inner
+            .toasts
+            .retain(|toast| toast.shown.elapsed() < Self::TOAST_TIME);
+        inner.toasts.clone()
+    }
+}
+
+/// A `tracing` layer filling a [`Log`].
+pub struct LogLayer {
+    log: Log,
+}
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::INFO {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        if level <= Level::WARN {
+            self.log.toast(level, visitor.message.clone());
+        }
+        self.log.push(Record {
+            time: Local::now(),
+            level,
+            target: event.metadata().target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+/// The message of an event, followed by any other fields.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message.insert_str(0, &format!("{value:?}"));
+        } else {
+            self.message
+                .push_str(&format!(" {}={value:?}", field.name()));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.insert_str(0, value);
+        } else {
+            self.message
+                .push_str(&format!(" {}={value:?}", field.name()));
+        }
+    }
+}
@@ -1,33 +1,165 @@
+pub mod error;
 pub mod events;
+pub mod history;
+pub mod keymap;
+pub mod log;
+pub mod palette;
+pub mod role;
+pub mod shared;
 
-use ratatui::widgets::ListState;
-use tui_textarea::TextArea;
+use std::{fs, path::Path, sync::Arc};
 
-use crate::sys::{
-    self,
-    data::{DumbRecipe, Reposotory},
-    db,
+use measurements::Volume;
+use ratatui::{
+    layout::{Position, Rect},
+    widgets::ListState,
 };
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tui_textarea::{CursorMove, TextArea};
 
-#[derive(Debug, Clone, Copy)]
+use crate::{
+    app::{
+        error::AppError,
+        history::{Change, History, Step},
+        keymap::{Action, Keymap},
+        log::Log,
+        palette::Palette,
+        role::{Access, Role},
+        shared::{Collection, SharedRepo},
+    },
+    sys::{
+        self,
+        data::{DumbRecipe, Reposotory},
+        db,
+        glass::HouseGlass,
+        photo,
+        query::{Query, QueryError, SavedQueries},
+        recipe::{Product, Recipe},
+        search::{Filter, Hit},
+    },
+    ui::{photo::PhotoState, theme::Theme},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CurrentMode {
     Main,
     Editing,
+    Searching,
+    Filtering,
+    /// Picking an action from the command palette.
+    Palette,
+    /// Asking before deleting the current recipe.
+    Deleting,
     Exiting,
 }
 
+impl CurrentMode {
+    pub const ALL: [CurrentMode; 7] = [
+        CurrentMode::Main,
+        CurrentMode::Editing,
+        CurrentMode::Searching,
+        CurrentMode::Filtering,
+        CurrentMode::Palette,
+        CurrentMode::Deleting,
+        CurrentMode::Exiting,
+    ];
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum CurrentScreen {
     Recipes,
     Ingredients,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CurrentlyEditing {
     Name,
-    Ingredients,
     Description,
-    // TODO:
+    Photo,
+    /// A new name for the current recipe.
+    Rename,
+    /// The name of a copy of the current recipe.
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterField {
+    Glassware,
+    BaseSpirit,
+    Ingredient,
+    Abv,
+}
+
+impl FilterField {
+    pub const ALL: [FilterField; 4] = [
+        FilterField::Glassware,
+        FilterField::BaseSpirit,
+        FilterField::Ingredient,
+        FilterField::Abv,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            FilterField::Glassware => "Glassware",
+            FilterField::BaseSpirit => "Base spirit",
+            FilterField::Ingredient => "Contains ingredient",
+            FilterField::Abv => "ABV range (e.g. 10-25)",
+        }
+    }
+
+    pub fn next(&self) -> FilterField {
+        let i = Self::ALL.iter().position(|f| f == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// A window shown on top of everything, closed by any key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    Help,
+    History,
+    Log,
+}
+
+/// Something on screen that can be clicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Action(Action),
+    FilterField(FilterField),
+    Editing(CurrentlyEditing),
+    /// A line of the command palette, by position.
+    PaletteEntry(usize),
+}
+
+/// Where things were drawn in the last frame, to make sense of the mouse.
+#[derive(Debug, Clone, Default)]
+pub struct Regions {
+    /// The area split between the recipe list and the recipe.
+    pub panes: Rect,
+    pub list: Rect,
+    pub card: Rect,
+    pub targets: Vec<(Rect, Target)>,
+}
+
+impl Regions {
+    /// The topmost target at `position`, popups are drawn last.
+    pub fn target_at(&self, position: Position) -> Option<Target> {
+        self.targets
+            .iter()
+            .rev()
+            .find(|(area, _)| area.contains(position))
+            .map(|(_, target)| *target)
+    }
+
+    /// Whether `position` is on the edge between the list and the recipe.
+    pub fn on_split(&self, position: Position, split: u16) -> bool {
+        // In u32, a wide terminal times a percentage doesn't fit in a u16.
+        let edge = u32::from(self.panes.x) + u32::from(self.panes.width) * u32::from(split) / 100;
+        let edge = u16::try_from(edge).unwrap_or(u16::MAX);
+        self.panes.contains(position) && (edge.saturating_sub(1)..=edge).contains(&position.x)
+    }
 }
 
 #[derive(Debug)]
@@ -36,47 +168,839 @@ pub struct App {
     pub current_recipe: DumbRecipe, // the currently being edited json value.
     pub current_mode: CurrentMode, // the current screen the user is looking at, and will later determine what is rendered.
     pub currently_editing: Option<CurrentlyEditing>, // the optional state containing which of the key or value pair the user is editing. It is an option, because when the user is not directly editing a key-value pair, this will be set to `None`.
-    pub repo: Reposotory,
+    /// What the repository looked like when last synced, see
+    /// [`App::sync`].
+    pub repo: Arc<Reposotory>,
+    /// Who is using the app, changes are recorded under this name.
+    pub user: String,
+    /// Every collection this session can see, `collection` is the open one.
+    pub collections: Vec<Collection>,
+    pub collection: usize,
+    updates: watch::Receiver<Arc<Reposotory>>,
     pub list_state: ListState,
-    pub recipes: Vec<String>,
     pub desc_text: TextArea<'static>,
     pub name_text: TextArea<'static>,
+    pub photo_text: TextArea<'static>,
+    pub new_name_text: TextArea<'static>,
+    pub photo: PhotoState,
+    /// The product the photo being attached is for, rather than the recipe.
+    pub photo_product: Option<String>,
+    pub search_text: TextArea<'static>,
+    pub filter: Filter,
+    pub filter_text: [TextArea<'static>; 4],
+    pub filter_field: FilterField,
+    pub saved_queries: SavedQueries,
+    pub keymap: Keymap,
+    pub palette: Palette,
+    pub overlay: Option<Overlay>,
+    pub history: History,
+    /// The history of each collection, while another one is open.
+    histories: Vec<History>,
+    pub theme: Theme,
+    pub regions: Regions,
+    /// Share of the width given to the recipe list, in percent.
+    pub split: u16,
+    /// The split is being dragged.
+    pub resizing: bool,
+    pub description_scroll: u16,
+    pub log: Log,
+    /// How far the log pane is scrolled up from the latest record.
+    pub log_scroll: usize,
+    /// The current recipe should be opened in `$EDITOR`, which only the
+    /// frontend owning the terminal can do.
+    pub open_in_editor: bool,
+    /// The app runs on the user's own machine and may use the files there,
+    /// see [`Access`].
+    pub files: bool,
+    /// A notice from whoever runs the app, like a server about to go down,
+    /// shown in place of the title.
+    pub banner: Option<String>,
     pub should_quit: bool,
 }
 
 impl App {
     pub fn new() -> App {
-        let mut repo = Reposotory::default();
-        repo.recipes.insert("Daiquri".to_string(), db::new_daiq());
-        repo.recipes.insert("Baiquri".to_string(), db::new_daiq());
-        repo.recipes.insert("Caiquri".to_string(), db::new_daiq());
-        repo.recipes.insert("aiquri".to_string(), db::new_daiq());
+        App::with_log(Log::default())
+    }
 
-        let recipes = Vec::from_iter(repo.recipes.keys().cloned());
+    /// An app reporting to `log`, which should already be fed by a
+    /// [`log::LogLayer`] so problems loading the config show up in it.
+    pub fn with_log(log: Log) -> App {
+        let user = std::env::var("USER").unwrap_or_else(|_| "local".to_string());
+        let house = Collection::house(SharedRepo::new(db::demo()), Role::Manager);
+        App {
+            files: true,
+            ..App::with_collections(user, vec![house], log)
+        }
+    }
+
+    /// An app for `user` working on `collections`, starting with the first,
+    /// and seeing the changes others make to them. Without any it gets an
+    /// empty house collection of its own. It may not use the files where it
+    /// runs, see [`App::files`].
+    pub fn with_collections(
+        user: impl Into<String>,
+        mut collections: Vec<Collection>,
+        log: Log,
+    ) -> App {
+        if collections.is_empty() {
+            let repo = SharedRepo::new(Reposotory::default());
+            collections.push(Collection::house(repo, Role::Manager));
+        }
+        let mut updates = collections[0].repo.subscribe();
+        let repo = updates.borrow_and_update().clone();
+        let histories = vec![History::default(); collections.len()];
         App {
             repo,
-            recipes,
+            user: user.into(),
+            collections,
+            collection: 0,
+            updates,
             current_recipe: sys::db::new_daiq().dumb(),
             current_mode: CurrentMode::Main,
             current_screen: CurrentScreen::Recipes,
             currently_editing: None,
             desc_text: TextArea::default(),
             name_text: TextArea::default(),
+            photo_text: TextArea::default(),
+            new_name_text: TextArea::default(),
+            photo: PhotoState::new(),
+            photo_product: None,
+            search_text: TextArea::default(),
+            filter: Filter::default(),
+            filter_text: Default::default(),
+            filter_field: FilterField::Glassware,
+            saved_queries: SavedQueries::load().unwrap_or_else(|err| {
+                tracing::warn!("Could not load saved queries: {err}");
+                SavedQueries::default()
+            }),
+            keymap: Keymap::load().unwrap_or_else(|err| {
+                tracing::warn!("Could not load keymap: {err}");
+                Keymap::default()
+            }),
+            palette: Palette::default(),
+            overlay: None,
+            history: History::default(),
+            histories,
+            theme: Theme::load().unwrap_or_else(|err| {
+                tracing::warn!("Could not load theme: {err}");
+                Theme::default()
+            }),
+            regions: Regions::default(),
+            split: 50,
+            resizing: false,
+            description_scroll: 0,
+            log,
+            log_scroll: 0,
+            open_in_editor: false,
+            files: false,
+            banner: None,
             list_state: ListState::default(),
             should_quit: false,
         }
     }
 
-    pub fn save_current_recipe(&mut self) -> Option<()> {
+    /// Tell the user something went as asked.
+    pub fn notify(&self, message: impl Into<String>) {
+        let message = message.into();
+        tracing::info!("{message}");
+        self.log.toast(tracing::Level::INFO, message);
+    }
+
+    /// Tell the user why an action didn't work.
+    pub fn report(&self, err: &AppError) {
+        tracing::warn!("{err}");
+    }
+
+    /// Look up the products of `recipe`, saying which one is missing if
+    /// that fails.
+    fn enrich(&self, recipe: DumbRecipe) -> Result<Recipe, AppError> {
+        let missing = recipe
+            .ingredients
+            .iter()
+            .map(|(_, product)| product)
+            .find(|product| !self.repo.ingredients.contains_key(*product))
+            .cloned();
+        let name = recipe.name.clone();
+        self.repo
+            .enrich(recipe)
+            .ok_or_else(|| AppError::UnknownProduct {
+                recipe: name,
+                product: missing.unwrap_or_default(),
+            })
+    }
+
+    pub fn save_current_recipe(&mut self) -> Result<(), AppError> {
         let recipe = self.current_recipe.clone();
-        let recipe = self.repo.enrich(recipe)?;
+        let recipe = self.enrich(recipe)?;
 
-        self.repo.recipes.insert(recipe.name.clone(), recipe);
+        let name = recipe.name.clone();
+        self.apply(Step {
+            description: format!("Save {name}"),
+            changes: vec![Change::recipe(&self.repo, &name, Some(recipe))],
+        })?;
+        self.notify(format!("Saved {name}"));
 
         self.currently_editing = None;
+        Ok(())
+    }
+
+    /// Fill the edit window with the current recipe.
+    pub fn start_editing(&mut self) {
+        self.name_text = TextArea::new(vec![self.current_recipe.name.clone()]);
+        self.name_text.move_cursor(CursorMove::End);
+        self.desc_text = TextArea::new(
+            self.current_recipe
+                .description
+                .as_deref()
+                .unwrap_or_default()
+                .lines()
+                .map(str::to_string)
+                .collect(),
+        );
+        self.currently_editing = Some(CurrentlyEditing::Name);
+    }
+
+    /// Apply the edit window to the current recipe and save it, as one step.
+    pub fn commit_edit(&mut self) -> Result<(), AppError> {
+        let mut after = self.current_recipe.clone();
+        let name = self.name_text.lines().join(" ");
+        if !name.trim().is_empty() {
+            after.name = name.trim().to_string();
+        }
+        let description = self.desc_text.lines().join("\n");
+        after.description = (!description.trim().is_empty()).then_some(description);
+
+        let old_name = self.current_recipe.name.clone();
+        let renamed = after.name != old_name;
+        if renamed && self.repo.recipes.contains_key(&after.name) {
+            return Err(AppError::NameTaken(after.name));
+        }
+
+        let recipe = self.enrich(after.clone())?;
+        let name = recipe.name.clone();
+        let mut changes = vec![Change::Current {
+            before: self.current_recipe.clone(),
+            after,
+        }];
+        // A new name moves the recipe, like renaming it does.
+        if renamed && self.repo.recipes.contains_key(&old_name) {
+            changes.push(Change::recipe(&self.repo, &old_name, None));
+        }
+        changes.push(Change::recipe(&self.repo, &name, Some(recipe)));
+        self.apply(Step {
+            description: format!("Edit {name}"),
+            changes,
+        })?;
+        self.notify(format!("Saved {name}"));
+        self.currently_editing = None;
+        Ok(())
+    }
+
+    /// Replace the current recipe with `after`, edited outside the app. It
+    /// is saved like any other edit.
+    pub fn replace_current_recipe(&mut self, after: DumbRecipe) -> Result<(), AppError> {
+        if after == self.current_recipe {
+            return Ok(());
+        }
+        self.apply(Step {
+            description: format!("Edit {} in $EDITOR", after.name),
+            changes: vec![Change::Current {
+                before: self.current_recipe.clone(),
+                after,
+            }],
+        })
+    }
+
+    /// Start a blank recipe, it is saved once edited.
+    pub fn new_recipe(&mut self) -> Result<(), AppError> {
+        let after = DumbRecipe {
+            name: self.free_name("New recipe"),
+            short_desc: None,
+            description: None,
+            ingredients: Vec::new(),
+            dilution: 0.0,
+            glassware: None,
+            glass: None,
+            layered: false,
+            on_the_rocks: false,
+            photo: None,
+            family: None,
+        };
+        self.apply(Step {
+            description: "New recipe".to_string(),
+            changes: vec![Change::Current {
+                before: self.current_recipe.clone(),
+                after,
+            }],
+        })?;
+        self.description_scroll = 0;
+        Ok(())
+    }
+
+    /// Ask for a name to rename or duplicate the current recipe to,
+    /// starting from `suggestion`.
+    pub fn start_naming(&mut self, editing: CurrentlyEditing) {
+        let suggestion = match editing {
+            CurrentlyEditing::Duplicate => {
+                self.free_name(&format!("{} variation", self.current_recipe.name))
+            }
+            _ => self.current_recipe.name.clone(),
+        };
+        self.new_name_text = TextArea::new(vec![suggestion]);
+        self.new_name_text.move_cursor(CursorMove::End);
+        self.currently_editing = Some(editing);
+    }
+
+    /// The first free name like `Daiquiri variation`, `Daiquiri variation 2`...
+    fn free_name(&self, base: &str) -> String {
+        let base = base.to_string();
+        if !self.repo.recipes.contains_key(&base) {
+            return base;
+        }
+        (2..)
+            .map(|i| format!("{base} {i}"))
+            .find(|name| !self.repo.recipes.contains_key(name))
+            .unwrap_or(base)
+    }
+
+    /// The name typed into `new_name_text`, if it is free to use.
+    fn new_name(&self) -> Result<String, AppError> {
+        let name = self.new_name_text.lines().join(" ").trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::EmptyName);
+        }
+        if self.repo.recipes.contains_key(&name) {
+            return Err(AppError::NameTaken(name));
+        }
+        Ok(name)
+    }
+
+    /// Move the current recipe to the name typed into `new_name_text`.
+    pub fn rename_current_recipe(&mut self) -> Result<(), AppError> {
+        let new_name = self.new_name()?;
+        let old_name = self.current_recipe.name.clone();
+        let after = DumbRecipe {
+            name: new_name.clone(),
+            ..self.current_recipe.clone()
+        };
+
+        let mut changes = Vec::new();
+        // A recipe that was never saved only changes name.
+        if self.repo.recipes.contains_key(&old_name) {
+            let recipe = self.enrich(after.clone())?;
+            changes.push(Change::recipe(&self.repo, &old_name, None));
+            changes.push(Change::recipe(&self.repo, &new_name, Some(recipe)));
+        }
+        changes.push(Change::Current {
+            before: self.current_recipe.clone(),
+            after,
+        });
+        self.apply(Step {
+            description: format!("Rename {old_name} to {new_name}"),
+            changes,
+        })?;
+        self.notify(format!("Renamed {old_name} to {new_name}"));
+        self.currently_editing = None;
+        Ok(())
+    }
+
+    /// Save a copy of the current recipe under the name typed into
+    /// `new_name_text`, and open it.
+    pub fn duplicate_current_recipe(&mut self) -> Result<(), AppError> {
+        let new_name = self.new_name()?;
+        let old_name = self.current_recipe.name.clone();
+        let after = DumbRecipe {
+            name: new_name.clone(),
+            ..self.current_recipe.clone()
+        };
+        let recipe = self.enrich(after.clone())?;
+
+        let changes = vec![
+            Change::recipe(&self.repo, &new_name, Some(recipe)),
+            Change::Current {
+                before: self.current_recipe.clone(),
+                after,
+            },
+        ];
+        self.apply(Step {
+            description: format!("Duplicate {old_name} as {new_name}"),
+            changes,
+        })?;
+        self.notify(format!("Duplicated {old_name} as {new_name}"));
+        self.currently_editing = None;
+        Ok(())
+    }
+
+    /// Remove the current recipe from the repository. It stays open, so it
+    /// can still be saved again.
+    pub fn delete_current_recipe(&mut self) -> Result<(), AppError> {
+        let name = self.current_recipe.name.clone();
+        if !self.repo.recipes.contains_key(&name) {
+            return Err(AppError::NotSaved(name));
+        }
+        self.apply(Step {
+            description: format!("Delete {name}"),
+            changes: vec![Change::recipe(&self.repo, &name, None)],
+        })?;
+        self.notify(format!("Deleted {name}"));
+        Ok(())
+    }
+
+    /// Take a new snapshot of the repository if someone changed it,
+    /// returning whether they did.
+    pub fn sync(&mut self) -> bool {
+        if !self.updates.has_changed().unwrap_or(false) {
+            return false;
+        }
+        self.repo = self.updates.borrow_and_update().clone();
+        true
+    }
+
+    /// The collection open now.
+    pub fn current_collection(&self) -> &Collection {
+        &self.collections[self.collection]
+    }
+
+    /// Open the next collection, each keeps its own undo history.
+    pub fn switch_collection(&mut self) {
+        let next = (self.collection + 1) % self.collections.len();
+        if next == self.collection {
+            self.notify(format!(
+                "{} is the only collection",
+                self.current_collection().name
+            ));
+            return;
+        }
+        std::mem::swap(&mut self.history, &mut self.histories[self.collection]);
+        std::mem::swap(&mut self.history, &mut self.histories[next]);
+        self.collection = next;
+        self.updates = self.collections[next].repo.subscribe();
+        self.repo = self.updates.borrow_and_update().clone();
+        self.list_state.select(None);
+        self.notify(format!(
+            "Opened the {} collection",
+            self.collections[next].name
+        ));
+    }
+
+    /// What the user may do with the open collection.
+    pub fn role(&self) -> Role {
+        self.current_collection().role
+    }
+
+    pub fn access(&self) -> Access {
+        Access {
+            role: self.role(),
+            files: self.files,
+        }
+    }
+
+    /// Note down who changed the open collection, for the others working
+    /// on it.
+    fn record(&self, description: &str) {
+        self.current_collection()
+            .repo
+            .record(&self.user, description);
+    }
+
+    /// Change the open collection with `f`, then take a new snapshot of it.
+    fn write<T>(
+        &mut self,
+        f: impl FnOnce(&mut History, &mut Reposotory, &mut DumbRecipe) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let collection = &self.collections[self.collection];
+        let (history, current_recipe) = (&mut self.history, &mut self.current_recipe);
+        let written = collection
+            .repo
+            .write(|repo| f(history, repo, current_recipe))?;
+        self.sync();
+        Ok(written)
+    }
+
+    /// Make `step` and remember it for undo. Fails without changing
+    /// anything when another session changed the same recipes first.
+    pub fn apply(&mut self, step: Step) -> Result<(), AppError> {
+        self.role().check_step(&step)?;
+        let recorded = step.changes_repo().then(|| step.description.clone());
+        self.write(|history, repo, current_recipe| {
+            step.applies_to(repo)?;
+            history.apply(step, repo, current_recipe);
+            Ok(())
+        })?;
+        if let Some(description) = recorded {
+            self.record(&description);
+        }
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Result<(), AppError> {
+        let step = self.history.next_undo();
+        if let Some(step) = &step {
+            self.role().check_step(step)?;
+        }
+        let undone = self.write(|history, repo, current_recipe| {
+            if let Some(step) = &step {
+                step.applies_to(repo)?;
+            }
+            Ok(history.undo(repo, current_recipe))
+        })?;
+        match (undone, step) {
+            (Some(description), Some(step)) => {
+                if step.changes_repo() {
+                    self.record(&format!("Undo {description}"));
+                }
+                self.notify(format!("Undid {description}"));
+            }
+            _ => self.notify("Nothing to undo"),
+        }
+        Ok(())
+    }
+
+    pub fn redo(&mut self) -> Result<(), AppError> {
+        let step = self.history.next_redo().cloned();
+        if let Some(step) = &step {
+            self.role().check_step(step)?;
+        }
+        let redone = self.write(|history, repo, current_recipe| {
+            if let Some(step) = &step {
+                step.applies_to(repo)?;
+            }
+            Ok(history.redo(repo, current_recipe))
+        })?;
+        match (redone, step) {
+            (Some(description), Some(step)) => {
+                if step.changes_repo() {
+                    self.record(&format!("Redo {description}"));
+                }
+                self.notify(format!("Redid {description}"));
+            }
+            _ => self.notify("Nothing to redo"),
+        }
+        Ok(())
+    }
+
+    /// Open the recipe selected in the list.
+    pub fn open_selected(&mut self) -> Option<()> {
+        let i = self.list_state.selected()?;
+        let hit = self.visible_recipes().into_iter().nth(i)?;
+        self.current_recipe = self.repo.recipes[&hit.name].clone().dumb();
+        self.description_scroll = 0;
         Some(())
     }
 
+    /// Show the command palette, asking for the argument of `action` if
+    /// there is one.
+    pub fn open_palette(&mut self, action: Option<Action>) {
+        self.palette = Palette::new(action, &self.repo);
+        self.current_mode = CurrentMode::Palette;
+    }
+
+    /// Switch to the theme called `name` for this session.
+    pub fn change_theme(&mut self, name: &str) -> Result<(), AppError> {
+        let theme = Theme::named(name).map_err(|err| AppError::Theme(err.to_string()))?;
+        self.theme = theme.fit(self.theme.color_support, self.theme.ascii);
+        self.notify(format!("Switched to the {name} theme"));
+        Ok(())
+    }
+
+    /// Write every recipe and product to `path` as TOML.
+    pub fn export(&self, path: &Path) -> Result<(), AppError> {
+        let failed = |reason: String| AppError::Export {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let text = toml::to_string(&*self.repo).map_err(|err| failed(err.to_string()))?;
+        fs::write(path, text).map_err(|err| failed(err.to_string()))?;
+        self.notify(format!("Exported recipes to {}", path.display()));
+        Ok(())
+    }
+
+    /// Add a house glass written like `Rocks, lowball, 280 ml`, or change
+    /// the one with the same name.
+    pub fn add_glass(&mut self, text: &str) -> Result<(), AppError> {
+        let glass = HouseGlass::parse(text).map_err(AppError::Glass)?;
+        let name = glass.name.clone();
+        let verb = if self.repo.glasses.contains_key(&name) {
+            "Change"
+        } else {
+            "Add"
+        };
+        self.apply(Step {
+            description: format!("{verb} glass {name}"),
+            changes: vec![Change::glass(&self.repo, &name, Some(glass))],
+        })?;
+        self.notify(format!("Saved the glass {name}"));
+        Ok(())
+    }
+
+    /// Remove the house glass called `name`. Recipes served in it go back to
+    /// the nominal capacity of their glassware.
+    pub fn remove_glass(&mut self, name: &str) -> Result<(), AppError> {
+        if !self.repo.glasses.contains_key(name) {
+            return Err(AppError::UnknownGlass(name.to_string()));
+        }
+        self.apply(Step {
+            description: format!("Remove glass {name}"),
+            changes: vec![Change::glass(&self.repo, name, None)],
+        })?;
+        self.notify(format!("Removed the glass {name}"));
+        Ok(())
+    }
+
+    /// Serve the current recipe in the house glass called `name`.
+    pub fn serve_in(&mut self, name: &str) -> Result<(), AppError> {
+        let glass = self
+            .repo
+            .glasses
+            .get(name)
+            .ok_or_else(|| AppError::UnknownGlass(name.to_string()))?;
+        let before = self.current_recipe.clone();
+        let after = DumbRecipe {
+            glassware: Some(glass.kind),
+            glass: Some(name.to_string()),
+            ..before.clone()
+        };
+        let recipe_name = after.name.clone();
+        let mut changes = vec![Change::Current {
+            before,
+            after: after.clone(),
+        }];
+        // Like any edit, recipes that were never saved get it once they are.
+        if self.repo.recipes.contains_key(&recipe_name) {
+            let recipe = self.enrich(after)?;
+            changes.push(Change::recipe(&self.repo, &recipe_name, Some(recipe)));
+        }
+        self.apply(Step {
+            description: format!("Serve {recipe_name} in {name}"),
+            changes,
+        })?;
+        self.notify(format!("Serving {recipe_name} in {name}"));
+        Ok(())
+    }
+
+    /// Log a drink of the current recipe as poured.
+    pub fn log_drink(&mut self) -> Result<(), AppError> {
+        let name = self.current_recipe.name.clone();
+        if !self.repo.recipes.contains_key(&name) {
+            return Err(AppError::NotSaved(name));
+        }
+        let poured = self.repo.poured.get(&name).copied().unwrap_or(0) + 1;
+        self.apply(Step {
+            description: format!("Pour {name}"),
+            changes: vec![Change::poured(&self.repo, &name, poured)],
+        })?;
+        self.notify(format!("Poured {name}, {poured} so far"));
+        Ok(())
+    }
+
+    /// Set what a litre of a product costs, written like `Rum, 24.50`.
+    pub fn set_cost(&mut self, text: &str) -> Result<(), AppError> {
+        let (name, cost) = parse_amount(text, "price a litre")?;
+        self.change_product(
+            name,
+            format!("Cost {name} at {cost:.2} a litre"),
+            |product| {
+                product.cost = Some(cost);
+            },
+        )?;
+        self.notify(format!("{name} costs {cost:.2} a litre"));
+        Ok(())
+    }
+
+    /// Set how much of a product is in stock, written like `Rum, 2100 ml`.
+    pub fn set_stock(&mut self, text: &str) -> Result<(), AppError> {
+        let (name, milis) = parse_amount(text.trim_end_matches("ml"), "ml")?;
+        self.change_product(name, format!("Count {milis} ml of {name}"), |product| {
+            product.stock = Some(Volume::from_milliliters(milis));
+        })?;
+        self.notify(format!("{milis} ml of {name} in stock"));
+        Ok(())
+    }
+
+    /// Change the product called `name` with `update`.
+    fn change_product(
+        &mut self,
+        name: &str,
+        description: String,
+        update: impl FnOnce(&mut Product),
+    ) -> Result<(), AppError> {
+        let mut after = self
+            .repo
+            .ingredients
+            .get(name)
+            .cloned()
+            .ok_or_else(|| AppError::NoProduct(name.to_string()))?;
+        update(&mut after);
+        self.apply(Step {
+            description,
+            changes: vec![Change::product(&self.repo, name, Some(after))],
+        })
+    }
+
+    /// Move the split between the list and the recipe to `column`.
+    pub fn resize_split(&mut self, column: u16) {
+        const MIN: u16 = 20;
+        let panes = self.regions.panes;
+        let offset = u32::from(column.saturating_sub(panes.x));
+        let percent = offset * 100 / u32::from(panes.width.max(1));
+        self.split = u16::try_from(percent)
+            .unwrap_or(u16::MAX)
+            .clamp(MIN, 100 - MIN);
+    }
+
+    /// Recipes matching the search and filters, in the order they are listed.
+    ///
+    /// A search that doesn't parse is left out until it is fixed.
+    pub fn visible_recipes(&self) -> Vec<Hit> {
+        self.query()
+            .unwrap_or_default()
+            .run(&self.repo, &self.filter)
+    }
+
+    /// Parse the search as a query.
+    pub fn query(&self) -> Result<Query, QueryError> {
+        Query::parse(&self.search_query(), &self.saved_queries)
+    }
+
+    pub fn search_query(&self) -> String {
+        self.search_text.lines().join(" ")
+    }
+
+    pub fn clear_search(&mut self) {
+        self.search_text = TextArea::default();
+        self.list_state.select(None);
+    }
+
+    pub fn filter_text(&mut self, field: FilterField) -> &mut TextArea<'static> {
+        let i = FilterField::ALL
+            .iter()
+            .position(|f| *f == field)
+            .unwrap_or(0);
+        &mut self.filter_text[i]
+    }
+
+    /// Parse the filter fields into `filter`. Fields that don't parse are
+    /// left out of the filter.
+    pub fn apply_filter(&mut self) {
+        let text = |app: &mut App, field| {
+            let text = app.filter_text(field).lines().join(" ");
+            let text = text.trim();
+            (!text.is_empty()).then(|| text.to_string())
+        };
+
+        let glassware = text(self, FilterField::Glassware).and_then(|glass| {
+            let parsed = Filter::parse_glassware(&glass);
+            if parsed.is_none() {
+                tracing::warn!("Unknown glassware {glass:?}");
+            }
+            parsed
+        });
+        let (abv_min, abv_max) = text(self, FilterField::Abv)
+            .and_then(|abv| {
+                let parsed = Filter::parse_abv(&abv);
+                if parsed.is_none() {
+                    tracing::warn!("Could not parse ABV range {abv:?}");
+                }
+                parsed
+            })
+            .unwrap_or_default();
+
+        self.filter = Filter {
+            glassware,
+            base_spirit: text(self, FilterField::BaseSpirit),
+            ingredient: text(self, FilterField::Ingredient),
+            abv_min,
+            abv_max,
+        };
+        self.list_state.select(None);
+    }
+
+    /// Ask for a photo to attach to the product `name`.
+    pub fn choose_photo_product(&mut self, name: &str) -> Result<(), AppError> {
+        if !self.repo.ingredients.contains_key(name) {
+            return Err(AppError::NoProduct(name.to_string()));
+        }
+        self.photo_product = Some(name.to_string());
+        self.current_mode = CurrentMode::Editing;
+        self.currently_editing = Some(CurrentlyEditing::Photo);
+        Ok(())
+    }
+
+    /// Copy the photo typed into `photo_text` next to the data and attach
+    /// it to the current recipe, or to [`App::photo_product`] when set.
+    pub fn attach_photo(&mut self) -> Result<(), AppError> {
+        let product = self.photo_product.take();
+        self.access().check_action(if product.is_some() {
+            Action::ProductPhoto
+        } else {
+            Action::AttachPhoto
+        })?;
+        let source = self.photo_text.lines().join("");
+        let source = Path::new(source.trim());
+        let photo_error = |err: std::io::Error| AppError::Photo {
+            path: source.display().to_string(),
+            reason: err.to_string(),
+        };
+
+        let name = product
+            .clone()
+            .unwrap_or_else(|| self.current_recipe.name.clone());
+        let file = photo::file_name(source, &name).map_err(photo_error)?;
+        let changes = match &product {
+            Some(product) => {
+                let before = self
+                    .repo
+                    .ingredients
+                    .get(product)
+                    .ok_or_else(|| AppError::NoProduct(product.clone()))?;
+                let after = Product {
+                    photo: Some(file.clone()),
+                    ..before.clone()
+                };
+                vec![Change::product(&self.repo, product, Some(after))]
+            }
+            None => {
+                let before = self.current_recipe.clone();
+                let after = DumbRecipe {
+                    photo: Some(file.clone()),
+                    ..before.clone()
+                };
+                let mut changes = vec![Change::Current {
+                    before,
+                    after: after.clone(),
+                }];
+                // Recipes that were never saved only get the photo once they are.
+                if self.repo.recipes.contains_key(&name) {
+                    let recipe = self.enrich(after)?;
+                    changes.push(Change::recipe(&self.repo, &name, Some(recipe)));
+                }
+                changes
+            }
+        };
+        let step = Step {
+            description: format!("Attach photo to {name}"),
+            changes,
+        };
+        // Nothing is copied for someone who may not attach it.
+        self.role().check_step(&step)?;
+        let stored = photo::store(source, &file).map_err(photo_error)?;
+        if let Err(err) = self.apply(step) {
+            // Nothing uses the copy when someone else got there first.
+            if stored {
+                if let Err(err) = photo::remove(&file) {
+                    tracing::warn!("Could not remove the unused photo {file}: {err}");
+                }
+            }
+            return Err(err);
+        }
+
+        self.notify(format!("Attached a photo to {name}"));
+        self.photo.visible = true;
+        self.currently_editing = None;
+        Ok(())
+    }
+
     pub fn toggle_editing(&mut self) {
         if let Some(edit_mode) = &self.currently_editing {
             match edit_mode {
@@ -86,7 +1010,9 @@ impl App {
                 CurrentlyEditing::Description => {
                     self.currently_editing = Some(CurrentlyEditing::Name)
                 }
-                _ => todo!(),
+                CurrentlyEditing::Photo
+                | CurrentlyEditing::Rename
+                | CurrentlyEditing::Duplicate => {}
             };
         } else {
             self.currently_editing = Some(CurrentlyEditing::Name);
@@ -94,14 +1020,134 @@ impl App {
     }
 
     pub fn print_toml(&self) -> Result<(), toml::ser::Error> {
-        let output = toml::to_string(&self.repo)?;
+        let output = toml::to_string(&*self.repo)?;
         println!("{}", output);
         Ok(())
     }
 }
 
+/// A product and an amount for it, written like `Rum, 24.50`, where the
+/// amount is the `what` an error asks for.
+fn parse_amount<'a>(text: &'a str, what: &str) -> Result<(&'a str, f64), AppError> {
+    let (name, amount) = text
+        .split_once(',')
+        .ok_or_else(|| AppError::Amount(format!("write it as product, {what}")))?;
+    let amount = amount.trim();
+    let amount = amount
+        .parse()
+        .ok()
+        .filter(|amount: &f64| amount.is_finite() && *amount >= 0.0)
+        .ok_or_else(|| AppError::Amount(format!("{amount:?} is not a {what}")))?;
+    Ok((name.trim(), amount))
+}
+
 impl Default for App {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manager on the demo collection, with two of its recipes.
+    fn with_demo() -> (App, String, String) {
+        let house = Collection::house(SharedRepo::new(db::demo()), Role::Manager);
+        let app = App::with_collections("alice", vec![house], Log::default());
+        let mut names = app.repo.recipes.keys().cloned();
+        let first = names.next().expect("the demo has recipes");
+        let second = names.next().expect("the demo has recipes");
+        (app, first, second)
+    }
+
+    /// Open `name` and type `new_name` into the edit window.
+    fn edit_name(app: &mut App, name: &str, new_name: &str) -> Result<(), AppError> {
+        app.current_recipe = app.repo.recipes[name].clone().dumb();
+        app.start_editing();
+        app.name_text = TextArea::new(vec![new_name.to_string()]);
+        app.commit_edit()
+    }
+
+    #[test]
+    fn editing_the_name_moves_the_recipe() {
+        let (mut app, first, _) = with_demo();
+        edit_name(&mut app, &first, "Renamed").unwrap();
+        assert!(app.repo.recipes.contains_key("Renamed"));
+        assert!(!app.repo.recipes.contains_key(&first));
+
+        // One undo brings back the old name, with nothing left over.
+        app.undo().unwrap();
+        assert!(app.repo.recipes.contains_key(&first));
+        assert!(!app.repo.recipes.contains_key("Renamed"));
+    }
+
+    #[test]
+    fn editing_into_a_taken_name_fails() {
+        let (mut app, first, second) = with_demo();
+        let before = app.repo.recipes.clone();
+        assert_eq!(
+            edit_name(&mut app, &first, &second),
+            Err(AppError::NameTaken(second))
+        );
+        assert_eq!(app.repo.recipes, before);
+    }
+
+    #[test]
+    fn splits_wide_terminals() {
+        let mut app = App::with_collections("alice", vec![], Log::default());
+        app.regions.panes = Rect::new(0, 0, 1000, 50);
+
+        app.resize_split(700);
+        assert_eq!(app.split, 70);
+        assert!(app.regions.on_split(Position::new(700, 10), app.split));
+        assert!(!app.regions.on_split(Position::new(500, 10), app.split));
+
+        app.resize_split(999);
+        assert_eq!(app.split, 80);
+    }
+
+    #[test]
+    fn bartenders_pour_and_managers_keep_costs_and_stock() {
+        let repo = SharedRepo::new(db::demo());
+        let session = |user, role| {
+            let house = Collection::house(repo.clone(), role);
+            App::with_collections(user, vec![house], Log::default())
+        };
+        let mut guest = session("guest", Role::Guest);
+        let mut bartender = session("bob", Role::Bartender);
+        let mut manager = session("alice", Role::Manager);
+        let name = repo.snapshot().recipes.keys().next().cloned().unwrap();
+        for app in [&mut guest, &mut bartender, &mut manager] {
+            app.current_recipe = app.repo.recipes[&name].clone().dumb();
+        }
+
+        assert!(matches!(guest.log_drink(), Err(AppError::Forbidden { .. })));
+        bartender.log_drink().unwrap();
+        bartender.log_drink().unwrap();
+        assert_eq!(repo.snapshot().poured[&name], 2);
+        assert!(matches!(
+            bartender.set_cost("Rum, 24"),
+            Err(AppError::Forbidden { .. })
+        ));
+        assert!(matches!(
+            bartender.set_stock("Rum, 700 ml"),
+            Err(AppError::Forbidden { .. })
+        ));
+
+        manager.set_cost("Rum, 25").unwrap();
+        manager.set_stock("Rum, 700 ml").unwrap();
+        assert_eq!(
+            manager.set_cost("Rum"),
+            Err(AppError::Amount(
+                "write it as product, price a litre".to_string()
+            ))
+        );
+        let tally = manager.repo.tally(&manager.current_recipe);
+        assert_eq!(tally.poured, 2);
+        // 60 ml of rum a drink.
+        assert_eq!(tally.left, Some(11));
+        // Nobody said what the syrup and the lime cost yet.
+        assert_eq!(tally.cost, None);
+    }
+}
@@ -0,0 +1,166 @@
+//! The command palette: every action by its description, fuzzy matched, so
+//! nothing needs its keys remembered.
+
+use ratatui::widgets::ListState;
+use tui_textarea::TextArea;
+
+use crate::{
+    app::{
+        keymap::{Action, Keymap},
+        role::Access,
+        CurrentMode,
+    },
+    sys::{data::Reposotory, search::fuzzy_match},
+    ui::theme::Theme,
+};
+
+/// Actions that only make sense inside a popup, or would open the palette
+/// again.
+const HIDDEN: [Action; 4] = [
+    Action::Confirm,
+    Action::Cancel,
+    Action::NextField,
+    Action::Palette,
+];
+
+/// One line of the palette.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub action: Action,
+    /// The argument this line stands for, when asking for one.
+    pub argument: Option<String>,
+    pub label: String,
+    /// Char indices into `label` that matched what was typed.
+    pub highlight: Vec<usize>,
+    /// The keys bound to the action, like `u` or `ctrl-x u`.
+    pub keys: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Palette {
+    pub text: TextArea<'static>,
+    /// The action being asked an argument for, once one is picked.
+    pub action: Option<Action>,
+    /// Suggestions for the argument.
+    pub choices: Vec<String>,
+    pub list_state: ListState,
+}
+
+impl Palette {
+    /// A palette listing every action, or asking for the argument of
+    /// `action` with suggestions from `repo`.
+    pub fn new(action: Option<Action>, repo: &Reposotory) -> Palette {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        Palette {
+            text: TextArea::default(),
+            action,
+            choices: action
+                .map(|action| choices(action, repo))
+                .unwrap_or_default(),
+            list_state,
+        }
+    }
+
+    pub fn query(&self) -> String {
+        self.text.lines().join(" ")
+    }
+
+    /// The lines matching what was typed, best first. Actions `access`
+    /// doesn't allow are left out.
+    pub fn entries(&self, keymap: &Keymap, access: Access) -> Vec<Entry> {
+        let query = self.query();
+        let mut entries: Vec<(i64, Entry)> = match self.action {
+            None => keymap_entries(keymap, access)
+                .into_iter()
+                .filter_map(|mut entry| {
+                    let matched = fuzzy_match(&query, &entry.label)?;
+                    entry.highlight = matched.indices;
+                    Some((matched.score, entry))
+                })
+                .collect(),
+            Some(action) => self
+                .choices
+                .iter()
+                .filter_map(|choice| {
+                    let matched = fuzzy_match(&query, choice)?;
+                    Some((
+                        matched.score,
+                        argument_entry(action, choice.clone(), matched.indices),
+                    ))
+                })
+                .collect(),
+        };
+        entries.sort_by_key(|(score, _)| -score);
+        let mut entries: Vec<Entry> = entries.into_iter().map(|(_, entry)| entry).collect();
+
+        // Anything typed can be the argument, not only the suggestions.
+        let typed = query.trim();
+        if let Some(action) = self.action {
+            if !typed.is_empty() && !entries.iter().any(|entry| entry.label == typed) {
+                entries.push(argument_entry(action, typed.to_string(), Vec::new()));
+            }
+        }
+        entries
+    }
+
+    /// The entry under the cursor.
+    pub fn selected(&self, keymap: &Keymap, access: Access) -> Option<Entry> {
+        let entries = self.entries(keymap, access);
+        let i = self
+            .list_state
+            .selected()?
+            .min(entries.len().checked_sub(1)?);
+        entries.into_iter().nth(i)
+    }
+}
+
+fn keymap_entries(keymap: &Keymap, access: Access) -> Vec<Entry> {
+    let bound = keymap.actions(CurrentMode::Main);
+    Action::ALL
+        .into_iter()
+        .filter(|action| !HIDDEN.contains(action) && access.allows(*action))
+        .map(|action| {
+            let keys = bound
+                .iter()
+                .find(|(bound, _)| *bound == action)
+                .map(|(_, chords)| {
+                    let chords: Vec<_> = chords.iter().map(ToString::to_string).collect();
+                    chords.join(", ")
+                })
+                .unwrap_or_default();
+            let mut label = action.description().to_string();
+            if let Some(argument) = action.argument() {
+                label = format!("{label} <{argument}>");
+            }
+            Entry {
+                action,
+                argument: None,
+                label,
+                highlight: Vec::new(),
+                keys,
+            }
+        })
+        .collect()
+}
+
+fn argument_entry(action: Action, argument: String, highlight: Vec<usize>) -> Entry {
+    Entry {
+        action,
+        label: argument.clone(),
+        argument: Some(argument),
+        highlight,
+        keys: String::new(),
+    }
+}
+
+/// Suggestions for the argument of `action`.
+fn choices(action: Action, repo: &Reposotory) -> Vec<String> {
+    match action {
+        Action::Theme => Theme::available(),
+        Action::Export => vec!["recipes.toml".to_string()],
+        Action::RemoveGlass | Action::ServeIn => repo.glasses.keys().cloned().collect(),
+        Action::ProductPhoto => repo.ingredients.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
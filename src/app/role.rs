@@ -0,0 +1,192 @@
+//! What someone may do with a collection.
+//!
+//! Guests look at the menu. Bartenders log the drinks they pour and keep
+//! the notes and photos up to date. Managers change the specs, and look
+//! after the products, what they cost and how much is in stock.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{
+        error::AppError,
+        history::{Change, Step},
+        keymap::Action,
+    },
+    sys::recipe::{Product, Recipe},
+};
+
+/// Each role may do everything the ones before it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Looks at the menu, changes nothing.
+    Guest,
+    /// Logs drinks, and keeps the notes of recipes and the photos of
+    /// recipes and products up to date.
+    Bartender,
+    /// Changes the specs, adds and removes recipes, and looks after the
+    /// products, their costs and stock, and the glasses.
+    Manager,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Guest, Role::Bartender, Role::Manager];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::Bartender => "bartender",
+            Role::Manager => "manager",
+        }
+    }
+
+    /// Whether the role may ask for `action` at all. Those it may not are
+    /// hidden from it.
+    pub fn allows(&self, action: Action) -> bool {
+        let needed = match action {
+            Action::NewRecipe
+            | Action::Delete
+            | Action::Rename
+            | Action::Duplicate
+            | Action::AddGlass
+            | Action::RemoveGlass
+            | Action::ServeIn
+            | Action::SetCost
+            | Action::SetStock
+            | Action::Export => Role::Manager,
+            Action::LogDrink
+            | Action::Edit
+            | Action::Save
+            | Action::AttachPhoto
+            | Action::ProductPhoto
+            | Action::OpenInEditor
+            | Action::Undo
+            | Action::Redo => Role::Bartender,
+            _ => Role::Guest,
+        };
+        *self >= needed
+    }
+
+    /// Fails when `action` isn't for this role.
+    pub fn check_action(&self, action: Action) -> Result<(), AppError> {
+        if self.allows(action) {
+            return Ok(());
+        }
+        Err(AppError::Forbidden {
+            role: *self,
+            what: action.description().to_string(),
+        })
+    }
+
+    /// Fails when `step` changes more than this role may, like a bartender
+    /// changing the spec of a recipe.
+    pub fn check_step(&self, step: &Step) -> Result<(), AppError> {
+        step.changes
+            .iter()
+            .try_for_each(|change| self.check_change(change))
+    }
+
+    fn check_change(&self, change: &Change) -> Result<(), AppError> {
+        let what = match (self, change) {
+            (_, Change::Current { .. }) | (Role::Manager, _) => return Ok(()),
+            (Role::Guest, Change::Recipe { name, .. }) => format!("change {name}"),
+            (Role::Guest, Change::Poured { .. }) => "log drinks".to_string(),
+            (Role::Bartender, Change::Poured { .. }) => return Ok(()),
+            (
+                Role::Bartender,
+                Change::Recipe {
+                    name,
+                    before: Some(before),
+                    after: Some(after),
+                },
+            ) => {
+                if without_notes(before) == without_notes(after) {
+                    return Ok(());
+                }
+                format!("change the spec of {name}")
+            }
+            (Role::Bartender, Change::Recipe { .. }) => "add or remove recipes".to_string(),
+            (
+                Role::Bartender,
+                Change::Product {
+                    name,
+                    before: Some(before),
+                    after: Some(after),
+                },
+            ) => {
+                if without_photo(before) == without_photo(after) {
+                    return Ok(());
+                }
+                format!("change the product {name}")
+            }
+            (_, Change::Product { name, .. }) => format!("change the product {name}"),
+            (_, Change::Glass { name, .. }) => format!("change the glass {name}"),
+        };
+        Err(AppError::Forbidden { role: *self, what })
+    }
+}
+
+/// What a session may do: its role in the open collection, and whether it
+/// may use the files where the app runs.
+///
+/// Only someone running the app on their own machine may, a session on a
+/// server never does, whatever its role. Being the manager of your own
+/// collection there is no business with the server's files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub role: Role,
+    pub files: bool,
+}
+
+impl Access {
+    /// Whether `action` may be asked for at all. Those that may not are
+    /// hidden.
+    pub fn allows(&self, action: Action) -> bool {
+        self.role.allows(action) && (self.files || !action.uses_files())
+    }
+
+    /// Fails when `action` isn't for this session.
+    pub fn check_action(&self, action: Action) -> Result<(), AppError> {
+        if !self.files && action.uses_files() {
+            return Err(AppError::Unsupported("Reading and writing files"));
+        }
+        self.role.check_action(action)
+    }
+}
+
+/// `recipe` without what a bartender may change.
+fn without_notes(recipe: &Recipe) -> Recipe {
+    Recipe {
+        short_desc: None,
+        description: None,
+        photo: None,
+        ..recipe.clone()
+    }
+}
+
+/// `product` without what a bartender may change.
+fn without_photo(product: &Product) -> Product {
+    Product {
+        photo: None,
+        ..product.clone()
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.name() == s)
+            .ok_or_else(|| format!("Unknown role {s:?}"))
+    }
+}
@@ -0,0 +1,294 @@
+//! The repository every session works on. Changes are made one at a time,
+//! and every session sees them as soon as they are made.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+    app::{error::AppError, history::Step, role::Role},
+    sys::data::Reposotory,
+};
+
+/// A change made to a shared repository, and who made it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    pub time: DateTime<Local>,
+    pub author: String,
+    pub description: String,
+}
+
+#[derive(Debug)]
+struct Inner {
+    repo: watch::Sender<Arc<Reposotory>>,
+    journal: Mutex<VecDeque<Edit>>,
+}
+
+/// A repository shared between sessions. Each session draws from its own
+/// snapshot, and takes a new one when the repository changes.
+#[derive(Debug, Clone)]
+pub struct SharedRepo(Arc<Inner>);
+
+impl SharedRepo {
+    /// Edits kept in the journal before the oldest are dropped.
+    const JOURNAL: usize = 1000;
+
+    pub fn new(repo: Reposotory) -> SharedRepo {
+        SharedRepo::with_journal(repo, Vec::new())
+    }
+
+    /// A repository whose journal goes on from `journal`, as it was saved
+    /// with it.
+    pub fn with_journal(repo: Reposotory, journal: Vec<Edit>) -> SharedRepo {
+        let skipped = journal.len().saturating_sub(Self::JOURNAL);
+        SharedRepo(Arc::new(Inner {
+            repo: watch::Sender::new(Arc::new(repo)),
+            journal: Mutex::new(journal.into_iter().skip(skipped).collect()),
+        }))
+    }
+
+    /// The repository as it is now.
+    pub fn snapshot(&self) -> Arc<Reposotory> {
+        self.0.repo.borrow().clone()
+    }
+
+    /// The repository as it is now, and every change after.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Reposotory>> {
+        self.0.repo.subscribe()
+    }
+
+    /// Note down that `author` made a change, described by `description`.
+    pub fn record(&self, author: &str, description: &str) {
+        let mut journal = self
+            .0
+            .journal
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if journal.len() == Self::JOURNAL {
+            journal.pop_front();
+        }
+        journal.push_back(Edit {
+            time: Local::now(),
+            author: author.to_string(),
+            description: description.to_string(),
+        });
+    }
+
+    /// Who changed what, oldest first.
+    pub fn journal(&self) -> Vec<Edit> {
+        let journal = self
+            .0
+            .journal
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        journal.iter().cloned().collect()
+    }
+
+    /// Change the repository with `f`, nobody else can change it meanwhile.
+    /// When `f` fails the repository stays as it was.
+    pub fn write<T>(
+        &self,
+        f: impl FnOnce(&mut Reposotory) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let mut result = None;
+        self.0.repo.send_if_modified(|repo| {
+            let mut changed = Reposotory::clone(repo);
+            let written = f(&mut changed);
+            let ok = written.is_ok();
+            if ok {
+                *repo = Arc::new(changed);
+            }
+            result = Some(written);
+            ok
+        });
+        result.expect("send_if_modified always runs the closure")
+    }
+}
+
+/// A repository as one session sees it, like the house recipes everyone
+/// shares, or the ones a user keeps to themselves.
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub name: String,
+    pub repo: SharedRepo,
+    /// What the session may do with it.
+    pub role: Role,
+}
+
+impl Collection {
+    /// The collection of the whole bar, which everyone sees.
+    pub const HOUSE: &'static str = "house";
+
+    pub fn house(repo: SharedRepo, role: Role) -> Collection {
+        Collection {
+            name: Collection::HOUSE.to_string(),
+            repo,
+            role,
+        }
+    }
+
+    /// Make `step` on behalf of `author`, unless the role doesn't allow it
+    /// or someone else changed the same things first.
+    pub fn apply(&self, author: &str, step: &Step) -> Result<(), AppError> {
+        self.role.check_step(step)?;
+        self.repo.write(|repo| {
+            step.applies_to(repo)?;
+            step.apply_to(repo);
+            Ok(())
+        })?;
+        self.repo.record(author, &step.description);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::{history::Change, log::Log, App},
+        sys::{db, recipe::Recipe},
+    };
+
+    /// Two managers on the same house collection.
+    fn sessions() -> (SharedRepo, App, App) {
+        let repo = SharedRepo::new(db::demo());
+        let session = |user| {
+            let house = Collection::house(repo.clone(), Role::Manager);
+            App::with_collections(user, vec![house], Log::default())
+        };
+        (repo.clone(), session("alice"), session("bob"))
+    }
+
+    fn some_recipe(repo: &Reposotory) -> Recipe {
+        repo.recipes
+            .values()
+            .next()
+            .cloned()
+            .expect("the demo has recipes")
+    }
+
+    /// A step giving `recipe` the description `text`, made from what `repo`
+    /// holds.
+    fn describe(repo: &Reposotory, recipe: &Recipe, text: &str) -> Step {
+        let after = Recipe {
+            description: Some(text.to_string()),
+            ..recipe.clone()
+        };
+        Step {
+            description: format!("Describe {}", recipe.name),
+            changes: vec![Change::recipe(repo, &recipe.name, Some(after))],
+        }
+    }
+
+    #[test]
+    fn second_session_to_change_a_recipe_conflicts() {
+        let (repo, mut alice, mut bob) = sessions();
+        let recipe = some_recipe(&alice.repo);
+        let alices = describe(&alice.repo, &recipe, "Shaken");
+        let bobs = describe(&bob.repo, &recipe, "Stirred");
+
+        alice.apply(alices).unwrap();
+        assert_eq!(
+            bob.apply(bobs),
+            Err(AppError::Conflict(recipe.name.clone()))
+        );
+
+        // Bob sees what Alice did, and nothing of his own got in.
+        assert!(bob.sync());
+        assert_eq!(alice.repo.recipes, bob.repo.recipes);
+        assert_eq!(alice.repo.recipes, repo.snapshot().recipes);
+        assert_eq!(
+            repo.snapshot().recipes[&recipe.name].description.as_deref(),
+            Some("Shaken")
+        );
+        let authors: Vec<_> = repo.journal().into_iter().map(|edit| edit.author).collect();
+        assert_eq!(authors, ["alice"]);
+    }
+
+    #[test]
+    fn sessions_converge_once_the_loser_tries_again() {
+        let (repo, mut alice, mut bob) = sessions();
+        let recipe = some_recipe(&alice.repo);
+        let bobs = describe(&bob.repo, &recipe, "Stirred");
+        alice
+            .apply(describe(&alice.repo, &recipe, "Shaken"))
+            .unwrap();
+        assert!(bob.apply(bobs).is_err());
+
+        bob.sync();
+        let recipe = bob.repo.recipes[&recipe.name].clone();
+        bob.apply(describe(&bob.repo, &recipe, "Stirred")).unwrap();
+
+        alice.sync();
+        assert_eq!(alice.repo.recipes, bob.repo.recipes);
+        assert_eq!(alice.repo.recipes, repo.snapshot().recipes);
+        assert_eq!(
+            alice.repo.recipes[&recipe.name].description.as_deref(),
+            Some("Stirred")
+        );
+    }
+
+    #[test]
+    fn undoing_what_another_session_changed_since_conflicts() {
+        let (repo, mut alice, mut bob) = sessions();
+        let recipe = some_recipe(&alice.repo);
+        alice
+            .apply(describe(&alice.repo, &recipe, "Shaken"))
+            .unwrap();
+        bob.sync();
+        let shaken = bob.repo.recipes[&recipe.name].clone();
+        bob.apply(describe(&bob.repo, &shaken, "Stirred")).unwrap();
+
+        assert_eq!(alice.undo(), Err(AppError::Conflict(recipe.name.clone())));
+        alice.sync();
+        assert_eq!(alice.repo.recipes, bob.repo.recipes);
+        assert_eq!(
+            repo.snapshot().recipes[&recipe.name].description.as_deref(),
+            Some("Stirred")
+        );
+    }
+
+    #[test]
+    fn collection_apply_conflicts_with_a_session() {
+        let (repo, mut alice, _) = sessions();
+        let house = Collection::house(repo.clone(), Role::Manager);
+        let recipe = some_recipe(&alice.repo);
+        let stale = describe(&repo.snapshot(), &recipe, "Over sftp");
+
+        alice
+            .apply(describe(&alice.repo, &recipe, "Shaken"))
+            .unwrap();
+        assert_eq!(
+            house.apply("carol", &stale),
+            Err(AppError::Conflict(recipe.name.clone()))
+        );
+
+        let fresh = describe(&repo.snapshot(), &recipe, "Over sftp");
+        house.apply("carol", &fresh).unwrap();
+        alice.sync();
+        assert_eq!(alice.repo.recipes, repo.snapshot().recipes);
+    }
+
+    #[test]
+    fn journal_goes_on_from_the_saved_one() {
+        let saved: Vec<_> = (0..SharedRepo::JOURNAL + 5)
+            .map(|i| Edit {
+                time: Local::now(),
+                author: "alice".to_string(),
+                description: format!("Edit {i}"),
+            })
+            .collect();
+        let repo = SharedRepo::with_journal(db::demo(), saved.clone());
+        repo.record("bob", "Edit more");
+
+        let journal = repo.journal();
+        assert_eq!(journal.len(), SharedRepo::JOURNAL);
+        assert_eq!(journal[0], saved[6]);
+        assert_eq!(journal.last().unwrap().author, "bob");
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+
+use clap::{Parser, Subcommand};
+use eyre::{eyre, Result};
+
+use crate::sys::{
+    data::Reposotory,
+    query::{Query, SavedQueries},
+    recipe::Recipe,
+    search::Filter,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Cocktail recipes and calculations")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the recipes matching a query, like `abv>20 has:lime sort:brix`
+    Query {
+        /// The query, the words are joined with spaces
+        query: Vec<String>,
+        /// Save the query under this name, to be used later as `@name`
+        #[arg(long)]
+        save: Option<String>,
+    },
+    /// List the saved queries
+    Queries,
+    /// List every recipe
+    List,
+    /// Show a recipe with its ingredients
+    Show {
+        /// The name of the recipe, the words are joined with spaces
+        name: Vec<String>,
+        /// Print the recipe as JSON, with the ingredients in ml
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the whole repository as TOML
+    Export,
+    /// List the house glasses with their capacities
+    Glasses,
+}
+
+pub fn run(command: Command, repo: &Reposotory, out: &mut dyn Write) -> Result<()> {
+    match command {
+        Command::Query { query, save } => {
+            let text = query.join(" ");
+            let mut saved = SavedQueries::load()?;
+            let query = Query::parse(&text, &saved)
+                .map_err(|err| eyre!("Invalid query\n{}", err.pointer(&text)))?;
+
+            for hit in query.run(repo, &Filter::default()) {
+                summary(&repo.recipes[&hit.name], out)?;
+            }
+
+            if let Some(name) = save {
+                saved.queries.insert(name, text);
+                saved.save()?;
+            }
+        }
+        Command::Queries => {
+            for (name, query) in SavedQueries::load()?.queries {
+                writeln!(out, "@{name}: {query}")?;
+            }
+        }
+        Command::List => {
+            for recipe in repo.recipes.values() {
+                summary(recipe, out)?;
+            }
+        }
+        Command::Show { name, json } => {
+            let name = name.join(" ");
+            let recipe = repo
+                .recipes
+                .get(&name)
+                .ok_or_else(|| eyre!("No recipe called {name:?}"))?;
+            if json {
+                writeln!(
+                    out,
+                    "{}",
+                    serde_json::to_string_pretty(&recipe.clone().dumb())?
+                )?;
+            } else {
+                show(recipe, out)?;
+            }
+        }
+        Command::Export => write!(out, "{}", toml::to_string(repo)?)?,
+        Command::Glasses => {
+            for glass in repo.glasses.values() {
+                writeln!(
+                    out,
+                    "{:<24} {:<12} {:>6.0} ml",
+                    glass.name,
+                    glass.kind.to_string(),
+                    glass.capacity.as_milliliters()
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One line with the name of `recipe`, its strength and volume.
+fn summary(recipe: &Recipe, out: &mut dyn Write) -> Result<()> {
+    writeln!(
+        out,
+        "{:<24} {:>5.1}% abv {:>6.0} ml",
+        recipe.name,
+        recipe.calc_abv(),
+        recipe.calc_volume().as_milliliters()
+    )?;
+    Ok(())
+}
+
+fn show(recipe: &Recipe, out: &mut dyn Write) -> Result<()> {
+    summary(recipe, out)?;
+    if let Some(short_desc) = &recipe.short_desc {
+        writeln!(out, "{short_desc}")?;
+    }
+    writeln!(out)?;
+    for (volume, product) in &recipe.ingredients {
+        writeln!(out, "{:>6.0} ml {}", volume.as_milliliters(), product.name)?;
+    }
+    match (&recipe.glass, recipe.glassware) {
+        (Some(glass), _) => writeln!(out, "\nServed in the {glass}")?,
+        (None, Some(glassware)) => writeln!(out, "\nServed in a {glassware}")?,
+        (None, None) => {}
+    }
+    if let Some(description) = &recipe.description {
+        writeln!(out, "\n{description}")?;
+    }
+    Ok(())
+}
@@ -1,4 +1,5 @@
 pub mod app;
+pub mod cli;
 pub mod sys;
 pub mod tui;
 pub mod ui;
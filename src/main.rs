@@ -1,4 +1,5 @@
 pub mod app;
+pub mod cli;
 pub mod sys;
 pub mod tui;
 pub mod ui;
@@ -6,14 +7,15 @@ pub mod ui;
 use std::{
     fs::File,
     io::{self, stdout, BufWriter, Read},
+    time::Duration,
 };
 
 use better_panic::Settings;
+use clap::Parser;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture, EventStream},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
 };
 use eyre::{OptionExt, Result};
 use futures::StreamExt;
@@ -22,8 +24,9 @@ use ratatui::{
     Terminal,
 };
 use serde::{de::DeserializeOwned, Serialize};
+use tracing_subscriber::layer::SubscriberExt;
 
-use crate::app::events;
+use crate::app::{events, log::Log};
 
 pub fn initialize_panic_handler() {
     std::panic::set_hook(Box::new(|panic_info| {
@@ -36,13 +39,27 @@ pub fn initialize_panic_handler() {
     }));
 }
 
+/// Hand the terminal over to `$EDITOR` to edit `edit` as TOML, and take it
+/// back even when that fails.
 fn edit_with_editor<B: Backend, T>(terminal: &mut Terminal<B>, edit: &mut T) -> Result<()>
 where
     T: Serialize + DeserializeOwned,
 {
-    stdout().execute(LeaveAlternateScreen)?;
+    execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
     disable_raw_mode()?;
 
+    let edited = run_editor(edit);
+
+    execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    enable_raw_mode()?;
+    terminal.clear()?;
+    edited
+}
+
+fn run_editor<T>(edit: &mut T) -> Result<()>
+where
+    T: Serialize + DeserializeOwned,
+{
     let mut file = tempfile::Builder::new()
         .prefix("calicomp-")
         .suffix(".toml")
@@ -66,15 +83,15 @@ where
     let payload: T = toml::from_str(&buf)?;
 
     *edit = payload;
-
-    stdout().execute(EnterAlternateScreen)?;
-    enable_raw_mode()?;
-    terminal.clear()?;
     Ok(())
 }
 
 async fn run_app<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
-    let mut app = app::App::new();
+    // Everything traced goes to the log pane, the terminal is taken.
+    let log = Log::default();
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(log.layer()))?;
+    let mut app = app::App::with_log(log);
+    app.photo = ui::photo::PhotoState::probe();
     let mut event_stream = EventStream::new();
 
     loop {
@@ -82,11 +99,29 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
             ui::entry(f, &mut app);
         })?;
 
-        let Some(event) = event_stream.next().await else {
+        let event = tokio::select! {
+            event = event_stream.next() => event,
+            // Redraw now and then, so toasts go away on their own.
+            _ = tokio::time::sleep(Duration::from_millis(500)) => continue,
+        };
+        let Some(event) = event else {
             tracing::info!("Event stream shutdown");
-            break Ok(())
+            break Ok(());
         };
-        app::events::update(&mut app, event?).await?;
+        if let Err(err) = app::events::update(&mut app, event?).await {
+            app.report(&err);
+        }
+        if std::mem::take(&mut app.open_in_editor) {
+            let mut recipe = app.current_recipe.clone();
+            match edit_with_editor(terminal, &mut recipe) {
+                Ok(()) => {
+                    if let Err(err) = app.replace_current_recipe(recipe) {
+                        app.report(&err);
+                    }
+                }
+                Err(err) => tracing::warn!("Could not edit in $EDITOR: {err}"),
+            }
+        }
         if app.should_quit {
             break Ok(());
         }
@@ -122,6 +157,9 @@ async fn init() -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // TODO setup clap
-    init().await
+    let cli = cli::Cli::parse();
+    match cli.command {
+        Some(command) => cli::run(command, &app::App::new().repo, &mut stdout()),
+        None => init().await,
+    }
 }
@@ -4,17 +4,131 @@ use measurements::Volume;
 use serde::{Deserialize, Serialize};
 
 use crate::sys::{
-    glass::Glassware,
+    family::Family,
+    glass::{Fit, Glassware, HouseGlass},
     recipe::{Product, Recipe},
 };
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Reposotory {
     pub recipes: BTreeMap<String, Recipe>,
     pub ingredients: BTreeMap<String, Product>,
+    #[serde(default)]
+    pub glasses: BTreeMap<String, HouseGlass>,
+    /// Drinks poured of each recipe, as logged by the bartenders.
+    #[serde(default)]
+    pub poured: BTreeMap<String, u32>,
+}
+
+/// How well a recipe fits its glass, and a better glass if there is one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlassCheck {
+    /// The house glass, or the kind of glass without one.
+    pub glass: String,
+    pub fit: Fit,
+    pub volume: Volume,
+    pub capacity: Volume,
+    pub suggestion: Option<String>,
+}
+
+/// How a recipe does behind the bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tally {
+    pub poured: u32,
+    /// What a drink costs, unless some product has no cost.
+    pub cost: Option<f64>,
+    /// How many more drinks the stock makes, when any of it was counted.
+    pub left: Option<u32>,
 }
 
 impl Reposotory {
+    /// The kind and capacity of the glass a recipe is served in: the house
+    /// glass called `glass`, or else the nominal capacity of `glassware`.
+    pub fn glass_for(
+        &self,
+        glass: Option<&str>,
+        glassware: Option<Glassware>,
+    ) -> Option<(Glassware, Volume)> {
+        match glass.and_then(|name| self.glasses.get(name)) {
+            Some(glass) => Some((glass.kind, glass.capacity)),
+            None => glassware.map(|kind| (kind, kind.capacity())),
+        }
+    }
+
+    pub fn check_glass(&self, recipe: &Recipe) -> Option<GlassCheck> {
+        let (kind, capacity) = self.glass_for(recipe.glass.as_deref(), recipe.glassware)?;
+        let glass = match &recipe.glass {
+            Some(name) if self.glasses.contains_key(name) => name.clone(),
+            _ => kind.to_string(),
+        };
+        let volume = recipe.calc_serve_volume();
+        let fit = Fit::of(volume, capacity);
+
+        let suggestion = match fit {
+            Fit::Good => None,
+            _ => self.suggest_glass(volume),
+        };
+        Some(GlassCheck {
+            glass,
+            fit,
+            volume,
+            capacity,
+            suggestion,
+        })
+    }
+
+    /// The glass from the house collection that `volume` fills closest to
+    /// the ideal share. Falls back to the standard glassware when the bar
+    /// hasn't recorded any glasses.
+    pub fn suggest_glass(&self, volume: Volume) -> Option<String> {
+        let candidates: Vec<(String, Volume)> = if self.glasses.is_empty() {
+            Glassware::ALL
+                .iter()
+                .map(|kind| (kind.to_string(), kind.capacity()))
+                .collect()
+        } else {
+            self.glasses
+                .values()
+                .map(|glass| (glass.name.clone(), glass.capacity))
+                .collect()
+        };
+
+        candidates
+            .into_iter()
+            .filter(|(_, capacity)| Fit::of(volume, *capacity) == Fit::Good)
+            .min_by(|(_, a), (_, b)| {
+                let off = |capacity: &Volume| (volume / *capacity - Fit::IDEAL).abs();
+                off(a).total_cmp(&off(b))
+            })
+            .map(|(name, _)| name)
+    }
+
+    /// How often `recipe` was poured, and what its products cost and how
+    /// far they go, going by the products as they are now.
+    pub fn tally(&self, recipe: &DumbRecipe) -> Tally {
+        let products = || {
+            recipe
+                .ingredients
+                .iter()
+                .map(|(milis, name)| (*milis, self.ingredients.get(name)))
+        };
+        let cost = products()
+            .map(|(milis, product)| Some(milis / 1000.0 * product?.cost?))
+            .sum();
+        let left = products()
+            .filter(|(milis, _)| *milis > 0.0)
+            .filter_map(|(milis, product)| {
+                let stock = product?.stock?.as_milliliters();
+                Some((stock / milis).floor().max(0.0) as u32)
+            })
+            .min();
+        Tally {
+            poured: self.poured.get(&recipe.name).copied().unwrap_or(0),
+            cost,
+            left,
+        }
+    }
+
     pub fn enrich(&self, recipe: DumbRecipe) -> Option<Recipe> {
         let DumbRecipe {
             name,
@@ -23,6 +137,11 @@ impl Reposotory {
             ingredients,
             dilution,
             glassware,
+            glass,
+            layered,
+            on_the_rocks,
+            photo,
+            family,
         } = recipe;
         let ingredients = ingredients
             .into_iter()
@@ -38,11 +157,16 @@ impl Reposotory {
             ingredients,
             dilution,
             glassware,
+            glass,
+            layered,
+            on_the_rocks,
+            photo,
+            family,
         })
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DumbRecipe {
     pub name: String,
 
@@ -60,6 +184,21 @@ pub struct DumbRecipe {
 
     #[serde(default)]
     pub glassware: Option<Glassware>,
+
+    #[serde(default)]
+    pub glass: Option<String>,
+
+    #[serde(default)]
+    pub layered: bool,
+
+    #[serde(default)]
+    pub on_the_rocks: bool,
+
+    #[serde(default)]
+    pub photo: Option<String>,
+
+    #[serde(default)]
+    pub family: Option<Family>,
 }
 
 #[derive(Clone, Debug)]
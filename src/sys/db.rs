@@ -1,11 +1,35 @@
 use measurements::Volume;
 
-use crate::sys::recipe::{Datasheet, Product, Recipe};
+use crate::sys::{
+    data::Reposotory,
+    recipe::{Datasheet, Product, Recipe},
+};
 
 pub fn generate() -> Vec<Recipe> {
     vec![new_daiq()]
 }
 
+/// A few recipes to start from, with the products they use.
+pub fn demo() -> Reposotory {
+    let mut repo = Reposotory::default();
+    for name in ["Daiquri", "Baiquri", "Caiquri", "aiquri"] {
+        let recipe = Recipe {
+            name: name.to_string(),
+            ..new_daiq()
+        };
+        repo.recipes.insert(recipe.name.clone(), recipe);
+    }
+    let products: Vec<_> = repo
+        .recipes
+        .values()
+        .flat_map(|recipe| recipe.ingredients.iter().map(|(_, p)| p.clone()))
+        .collect();
+    for product in products {
+        repo.ingredients.insert(product.name.clone(), product);
+    }
+    repo
+}
+
 pub fn new_daiq() -> Recipe {
     let rum = Product::builder()
         .datasheet(Datasheet::builder().abv(40.0).build())
@@ -18,7 +42,13 @@ pub fn new_daiq() -> Recipe {
         .build();
 
     let lime = Product::builder()
-        .datasheet(Datasheet::builder().acidity(6.0).brix(1.7).build())
+        .datasheet(
+            Datasheet::builder()
+                .acidity(6.0)
+                .brix(1.7)
+                .color([214, 226, 150])
+                .build(),
+        )
         .name("Lime Juice".to_string())
         .build();
 
@@ -31,6 +61,7 @@ pub fn new_daiq() -> Recipe {
         .name("Daiquiri".to_string())
         .dilution(20.0)
         .glassware(super::glass::Glassware::Martini)
+        .family(super::family::Family::Sour)
         .short_desc("Happy Hour / Summer drink".to_string())
         .description(
             "The daiquiri (/ˈdaɪkəri, ˈdæk-/; Spanish: daiquirí [dajkiˈɾi])\
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -15,3 +17,51 @@ pub enum Whiskey {
     Irish,
     Bourbon,
 }
+
+/// The family of drinks a recipe belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Family {
+    Sour,
+    OldFashioned,
+    Martini,
+    Highball,
+    Fizz,
+    Flip,
+    Julep,
+    Tiki,
+    Punch,
+    Spritz,
+}
+
+impl Family {
+    pub const ALL: [Family; 10] = [
+        Family::Sour,
+        Family::OldFashioned,
+        Family::Martini,
+        Family::Highball,
+        Family::Fizz,
+        Family::Flip,
+        Family::Julep,
+        Family::Tiki,
+        Family::Punch,
+        Family::Spritz,
+    ];
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Family::Sour => "Sour",
+            Family::OldFashioned => "Old Fashioned",
+            Family::Martini => "Martini",
+            Family::Highball => "Highball",
+            Family::Fizz => "Fizz",
+            Family::Flip => "Flip",
+            Family::Julep => "Julep",
+            Family::Tiki => "Tiki",
+            Family::Punch => "Punch",
+            Family::Spritz => "Spritz",
+        };
+        f.write_str(name)
+    }
+}
@@ -1,12 +1,146 @@
+use std::fmt;
+
+use crate::sys::search::Filter;
+
+use measurements::Volume;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Glassware {
     Lowball,
     Highball,
+    Collins,
     Martini,
     NickAndNora,
+    Coupe,
+    Flute,
     Hurricane,
     Tiki,
     Wine,
+    JulepCup,
+    Snifter,
+}
+
+impl Glassware {
+    pub const ALL: [Glassware; 12] = [
+        Glassware::Lowball,
+        Glassware::Highball,
+        Glassware::Collins,
+        Glassware::Martini,
+        Glassware::NickAndNora,
+        Glassware::Coupe,
+        Glassware::Flute,
+        Glassware::Hurricane,
+        Glassware::Tiki,
+        Glassware::Wine,
+        Glassware::JulepCup,
+        Glassware::Snifter,
+    ];
+
+    /// Typical capacity when filled to the brim.
+    pub fn capacity(&self) -> Volume {
+        let milis = match self {
+            Glassware::Lowball => 300.0,
+            Glassware::Highball => 350.0,
+            Glassware::Collins => 400.0,
+            Glassware::Martini => 200.0,
+            Glassware::NickAndNora => 150.0,
+            Glassware::Coupe => 180.0,
+            Glassware::Flute => 180.0,
+            Glassware::Hurricane => 450.0,
+            Glassware::Tiki => 400.0,
+            Glassware::Wine => 450.0,
+            Glassware::JulepCup => 350.0,
+            Glassware::Snifter => 500.0,
+        };
+        Volume::from_milliliters(milis)
+    }
+}
+
+impl fmt::Display for Glassware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Glassware::Lowball => "Lowball",
+            Glassware::Highball => "Highball",
+            Glassware::Collins => "Collins",
+            Glassware::Martini => "Martini",
+            Glassware::NickAndNora => "Nick & Nora",
+            Glassware::Coupe => "Coupe",
+            Glassware::Flute => "Flute",
+            Glassware::Hurricane => "Hurricane",
+            Glassware::Tiki => "Tiki mug",
+            Glassware::Wine => "Wine",
+            Glassware::JulepCup => "Julep cup",
+            Glassware::Snifter => "Snifter",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A glass the bar actually owns, with its measured capacity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HouseGlass {
+    pub name: String,
+    pub kind: Glassware,
+    pub capacity: Volume,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl HouseGlass {
+    /// Parse a glass written like `Rocks, lowball, 280 ml`, optionally
+    /// followed by a description.
+    pub fn parse(text: &str) -> Result<HouseGlass, String> {
+        let mut parts = text.splitn(4, ',').map(str::trim);
+        let (Some(name), Some(kind), Some(capacity)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("write it as name, glassware, capacity in ml".to_string());
+        };
+        if name.is_empty() {
+            return Err("a glass needs a name".to_string());
+        }
+        let kind =
+            Filter::parse_glassware(kind).ok_or_else(|| format!("unknown glassware {kind:?}"))?;
+        let milis: f64 = capacity
+            .trim_end_matches("ml")
+            .trim()
+            .parse()
+            .ok()
+            .filter(|milis: &f64| *milis > 0.0)
+            .ok_or_else(|| format!("{capacity:?} is not a capacity in ml"))?;
+        Ok(HouseGlass {
+            name: name.to_string(),
+            kind,
+            capacity: Volume::from_milliliters(milis),
+            description: parts
+                .next()
+                .filter(|description| !description.is_empty())
+                .map(str::to_string),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    Overflowing,
+    Underfilled,
+    Good,
+}
+
+impl Fit {
+    /// Below this share of the capacity the drink looks lost in the glass.
+    pub const UNDERFILLED: f64 = 0.5;
+    /// The share of the capacity a well-poured drink aims for.
+    pub const IDEAL: f64 = 0.75;
+
+    pub fn of(volume: Volume, capacity: Volume) -> Fit {
+        let share = volume / capacity;
+        if share > 1.0 {
+            Fit::Overflowing
+        } else if share < Self::UNDERFILLED {
+            Fit::Underfilled
+        } else {
+            Fit::Good
+        }
+    }
 }
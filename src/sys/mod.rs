@@ -2,4 +2,26 @@ pub mod data;
 pub mod db;
 pub mod family;
 pub mod glass;
+pub mod photo;
+pub mod query;
 pub mod recipe;
+pub mod search;
+
+use std::path::{Component, Path};
+
+use directories::ProjectDirs;
+
+/// Where calicomp keeps its configuration and data.
+pub fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "calicomp")
+}
+
+/// Whether `name` is a single plain file name, not `..`, an absolute path or
+/// anything with a separator in it, so joining it stays inside a directory.
+pub fn is_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
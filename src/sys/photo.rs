@@ -0,0 +1,69 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::sys::{is_file_name, project_dirs};
+
+/// Where attached photos are kept, next to the rest of the data.
+pub fn photo_dir() -> Option<PathBuf> {
+    Some(project_dirs()?.data_dir().join("photos"))
+}
+
+/// The file name the photo at `source` is stored under for `name`: the name
+/// made safe for a path, and a hash of the photo, so that recipes whose names
+/// only differ in punctuation don't overwrite each other's photo. The same
+/// photo always gets the same name.
+pub fn file_name(source: &Path, name: &str) -> io::Result<String> {
+    let hash = Sha256::digest(fs::read(source)?);
+    let hash: String = hash[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = format!("{stem}-{hash}");
+    Ok(match source.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{stem}.{ext}"),
+        None => stem,
+    })
+}
+
+/// Copy the photo at `source` into the photo directory as `file`, a name from
+/// [`file_name`].
+///
+/// Returns whether the file is new, rather than the same photo stored
+/// before.
+pub fn store(source: &Path, file: &str) -> io::Result<bool> {
+    let path = resolve(file).ok_or_else(|| io::Error::other("No data directory available"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let new = !path.exists();
+    fs::copy(source, path)?;
+    Ok(new)
+}
+
+/// Delete the stored photo `file`.
+pub fn remove(file: &str) -> io::Result<()> {
+    let path = resolve(file).ok_or_else(|| io::Error::other("No data directory available"))?;
+    fs::remove_file(path)
+}
+
+/// Full path of a stored photo. Names can come from anyone who writes the
+/// collection, so only plain file names inside the photo directory are
+/// resolved, never `..` or absolute paths.
+pub fn resolve(file: &str) -> Option<PathBuf> {
+    if !is_file_name(file) {
+        return None;
+    }
+    Some(photo_dir()?.join(file))
+}
@@ -0,0 +1,639 @@
+//! A small query language over recipes.
+//!
+//! A query is a list of terms separated by spaces, all of which a recipe
+//! has to match:
+//!
+//! - `glass:martini`, `has:lime`, `spirit:rum`, `family:sour`, `name:daiq`
+//! - `abv>20`, `brix<=10`, `volume=120`, `dilution!=0`
+//! - `sort:brix` or `sort:-abv` for descending order
+//! - `@name` to include a saved query
+//! - anything else is fuzzy matched against names, descriptions and ingredients
+//!
+//! Any term but `sort:` can be negated with a leading `-`, and values with
+//! spaces can be quoted, like `has:"lime juice"`.
+
+use std::{collections::BTreeMap, fmt, fs, ops::Range, path::PathBuf};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::sys::{
+    data::Reposotory,
+    family::Family,
+    glass::Glassware,
+    project_dirs,
+    recipe::Recipe,
+    search::{parse_name, text_match, Filter, Hit},
+};
+
+const KEYS: &str = "glass, has, spirit, family, name or sort";
+const PROPERTIES: &str = "abv, brix, volume or dilution";
+
+/// A calculated property of a recipe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    Abv,
+    Brix,
+    Volume,
+    Dilution,
+}
+
+impl Property {
+    fn parse(text: &str) -> Option<Property> {
+        match text.to_lowercase().as_str() {
+            "abv" => Some(Property::Abv),
+            "brix" => Some(Property::Brix),
+            "volume" | "vol" => Some(Property::Volume),
+            "dilution" => Some(Property::Dilution),
+            _ => None,
+        }
+    }
+
+    pub fn of(&self, recipe: &Recipe) -> f64 {
+        match self {
+            Property::Abv => recipe.calc_abv(),
+            Property::Brix => recipe.calc_brix(),
+            Property::Volume => recipe.calc_volume().as_milliliters(),
+            Property::Dilution => recipe.dilution,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Longest operators first, so `>=` isn't read as `>`.
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        ("!=", Comparison::NotEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ];
+
+    fn holds(&self, left: f64, right: f64) -> bool {
+        // Calculated values are rarely whole numbers, so compare equality
+        // at the precision people type.
+        let equal = (left - right).abs() < 0.05;
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left < right || equal,
+            Comparison::Equal => equal,
+            Comparison::NotEqual => !equal,
+            Comparison::GreaterOrEqual => left > right || equal,
+            Comparison::Greater => left > right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Glass(Glassware),
+    Has(String),
+    Spirit(String),
+    Family(Family),
+    Name(String),
+    Compare(Property, Comparison, f64),
+    Text(String),
+}
+
+impl Predicate {
+    fn matches(&self, name: &str, recipe: &Recipe) -> bool {
+        let contains =
+            |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        match self {
+            Predicate::Glass(glass) => recipe.glassware == Some(*glass),
+            Predicate::Has(ingredient) => recipe
+                .ingredients
+                .iter()
+                .any(|(_, product)| contains(&product.name, ingredient)),
+            Predicate::Spirit(spirit) => recipe
+                .base_spirit()
+                .is_some_and(|base| contains(&base.name, spirit)),
+            Predicate::Family(family) => recipe.family == Some(*family),
+            Predicate::Name(part) => contains(name, part),
+            Predicate::Compare(property, comparison, value) => {
+                comparison.holds(property.of(recipe), *value)
+            }
+            Predicate::Text(text) => text_match(text, name, recipe).is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub predicate: Predicate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Property(Property),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+    pub sort: Option<Sort>,
+}
+
+/// What went wrong parsing a query, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    /// Char offsets into the query.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        QueryError {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// The query with the offending part underlined, followed by the message.
+    pub fn pointer(&self, query: &str) -> String {
+        let width = self.span.len().max(1);
+        format!(
+            "{query}\n{}{} {}",
+            " ".repeat(self.span.start),
+            "^".repeat(width),
+            self.message
+        )
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at column {})", self.message, self.span.start + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// A word of the query, with quotes removed, and where it was.
+struct Token {
+    text: String,
+    span: Range<usize>,
+    /// Where each char of `text` is in the query.
+    offsets: Vec<usize>,
+}
+
+impl Token {
+    /// Where the token goes on from char `from` of `text`, to its end. Quotes
+    /// in between are part of it, so `has:"lime juice"` has its value from
+    /// the opening quote on.
+    fn span_from(&self, from: usize) -> Range<usize> {
+        let start = match from.checked_sub(1) {
+            Some(last) => self
+                .offsets
+                .get(last)
+                .map_or(self.span.end, |offset| offset + 1),
+            None => self.span.start,
+        };
+        start..self.span.end
+    }
+
+    /// Where the `len` chars of `text` from char `from` on are, up to the
+    /// char after them, so with any quotes in between.
+    fn span_of(&self, from: usize, len: usize) -> Range<usize> {
+        let end = self
+            .offsets
+            .get(from + len)
+            .copied()
+            .unwrap_or(self.span.end);
+        self.span_from(from).start..end
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().enumerate().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut offsets = Vec::new();
+        let mut end = start;
+        let mut quote: Option<usize> = None;
+        while let Some(&(i, c)) = chars.peek() {
+            if quote.is_none() && c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            end = i + 1;
+            match (c, quote) {
+                ('"', None) => quote = Some(i),
+                ('"', Some(_)) => quote = None,
+                _ => {
+                    text.push(c);
+                    offsets.push(i);
+                }
+            }
+        }
+        if let Some(open) = quote {
+            return Err(QueryError::new(open..end, "unterminated quote"));
+        }
+        tokens.push(Token {
+            text,
+            span: start..end,
+            offsets,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Named queries kept in the config directory, used with `@name`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedQueries {
+    #[serde(flatten)]
+    pub queries: BTreeMap<String, String>,
+}
+
+impl SavedQueries {
+    pub fn path() -> Option<PathBuf> {
+        Some(project_dirs()?.config_dir().join("queries.toml"))
+    }
+
+    /// Load the saved queries, or none if nothing has been saved yet.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Ok(Self::default());
+        };
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or_else(|| eyre::eyre!("No config directory available"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str, saved: &SavedQueries) -> Result<Query, QueryError> {
+        Self::parse_nested(query, saved, &mut Vec::new())
+    }
+
+    /// `expanding` holds the saved queries we are inside of, to catch cycles.
+    fn parse_nested(
+        query: &str,
+        saved: &SavedQueries,
+        expanding: &mut Vec<String>,
+    ) -> Result<Query, QueryError> {
+        let mut parsed = Query::default();
+        for token in tokenize(query)? {
+            let (negated, text) = match token.text.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, token.text.as_str()),
+            };
+            // Leave the `-` out, so errors point at the term itself.
+            let skip = usize::from(negated);
+            let span = token.span_from(skip);
+
+            if let Some(name) = text.strip_prefix('@') {
+                if negated {
+                    return Err(QueryError::new(span, "saved queries can't be negated"));
+                }
+                let Some(body) = saved.queries.get(name) else {
+                    return Err(QueryError::new(span, format!("no saved query `{name}`")));
+                };
+                if expanding.iter().any(|outer| outer == name) {
+                    return Err(QueryError::new(
+                        span,
+                        format!("saved query `{name}` includes itself"),
+                    ));
+                }
+                expanding.push(name.to_string());
+                let inner = Self::parse_nested(body, saved, expanding).map_err(|err| {
+                    QueryError::new(span.clone(), format!("in `@{name}`: {}", err.message))
+                })?;
+                expanding.pop();
+
+                parsed.terms.extend(inner.terms);
+                if inner.sort.is_some() && parsed.sort.is_none() {
+                    parsed.sort = inner.sort;
+                }
+                continue;
+            }
+
+            if let Some((key, value)) = text.split_once(':') {
+                if key.eq_ignore_ascii_case("sort") {
+                    if negated {
+                        return Err(QueryError::new(
+                            span,
+                            "`sort:` can't be negated, use `sort:-` to reverse it",
+                        ));
+                    }
+                    if parsed.sort.is_some() {
+                        return Err(QueryError::new(span, "only one `sort:` is allowed"));
+                    }
+                    let value_at = skip + key.chars().count() + 1;
+                    parsed.sort = Some(parse_sort(value, &token, value_at)?);
+                } else {
+                    let predicate = parse_key(key, value, &token, skip)?;
+                    parsed.terms.push(Term { negated, predicate });
+                }
+                continue;
+            }
+
+            let predicate = match parse_comparison(text, &token, skip)? {
+                Some(comparison) => comparison,
+                None => Predicate::Text(text.to_string()),
+            };
+            parsed.terms.push(Term { negated, predicate });
+        }
+        Ok(parsed)
+    }
+
+    pub fn matches(&self, name: &str, recipe: &Recipe) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.predicate.matches(name, recipe) != term.negated)
+    }
+
+    /// The recipes in `repo` matching both the query and `filter`.
+    ///
+    /// Results follow `sort:` if given, otherwise the best fuzzy matches come
+    /// first, otherwise they are alphabetical.
+    pub fn run(&self, repo: &Reposotory, filter: &Filter) -> Vec<Hit> {
+        let texts: Vec<&str> = self
+            .terms
+            .iter()
+            .filter(|term| !term.negated)
+            .filter_map(|term| match &term.predicate {
+                Predicate::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let mut hits: Vec<(Hit, &Recipe)> = repo
+            .recipes
+            .iter()
+            .filter(|(name, recipe)| filter.matches(recipe) && self.matches(name, recipe))
+            .map(|(name, recipe)| {
+                let mut score = 0;
+                let mut highlight = Vec::new();
+                for (s, indices) in texts.iter().filter_map(|t| text_match(t, name, recipe)) {
+                    score += s;
+                    highlight.extend(indices);
+                }
+                let hit = Hit {
+                    name: name.clone(),
+                    score,
+                    highlight,
+                };
+                (hit, recipe)
+            })
+            .collect();
+
+        match self.sort {
+            Some(Sort { key, descending }) => {
+                hits.sort_by(|(a, ra), (b, rb)| {
+                    let order = match key {
+                        SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                        SortKey::Property(p) => p.of(ra).total_cmp(&p.of(rb)),
+                    };
+                    if descending {
+                        order.reverse()
+                    } else {
+                        order
+                    }
+                });
+            }
+            None if !texts.is_empty() => {
+                hits.sort_by_key(|(hit, _)| std::cmp::Reverse(hit.score));
+            }
+            None => {}
+        }
+        hits.into_iter().map(|(hit, _)| hit).collect()
+    }
+}
+
+/// Parse `key:value`, the term from char `at` of `token` on.
+fn parse_key(key: &str, value: &str, token: &Token, at: usize) -> Result<Predicate, QueryError> {
+    let span = token.span_from(at);
+    let key_chars = key.chars().count();
+    // Point errors about the value at the value, not the whole term.
+    let value_span = token.span_from(at + key_chars + 1);
+    if value.is_empty() {
+        return Err(QueryError::new(span, format!("`{key}:` needs a value")));
+    }
+
+    match key.to_lowercase().as_str() {
+        "glass" => parse_name(&Glassware::ALL, value)
+            .map(Predicate::Glass)
+            .ok_or_else(|| QueryError::new(value_span, format!("unknown glassware `{value}`"))),
+        "family" => parse_name(&Family::ALL, value)
+            .map(Predicate::Family)
+            .ok_or_else(|| QueryError::new(value_span, format!("unknown family `{value}`"))),
+        "has" => Ok(Predicate::Has(value.to_string())),
+        "spirit" => Ok(Predicate::Spirit(value.to_string())),
+        "name" => Ok(Predicate::Name(value.to_string())),
+        _ => {
+            let key_span = token.span_of(at, key_chars);
+            let hint = if Property::parse(key).is_some() {
+                format!(", to compare use `{key}>`, `{key}<` or `{key}=`")
+            } else {
+                String::new()
+            };
+            Err(QueryError::new(
+                key_span,
+                format!("unknown filter `{key}:`, expected {KEYS}{hint}"),
+            ))
+        }
+    }
+}
+
+/// Parse what comes after `sort:`, from char `at` of `token` on.
+fn parse_sort(value: &str, token: &Token, at: usize) -> Result<Sort, QueryError> {
+    let span = token.span_from(at);
+    let (descending, name) = match value.strip_prefix('-') {
+        Some(name) => (true, name),
+        None => (false, value),
+    };
+    let key = if name.eq_ignore_ascii_case("name") {
+        SortKey::Name
+    } else if let Some(property) = Property::parse(name) {
+        SortKey::Property(property)
+    } else if name.is_empty() {
+        return Err(QueryError::new(span, "`sort:` needs something to sort by"));
+    } else {
+        let name_span = token.span_from(at + usize::from(descending));
+        return Err(QueryError::new(
+            name_span,
+            format!("can't sort by `{name}`, expected name, {PROPERTIES}"),
+        ));
+    };
+    Ok(Sort { key, descending })
+}
+
+/// Parse `abv>20` and friends, the term from char `at` of `token` on. Words
+/// without an operator aren't comparisons.
+fn parse_comparison(text: &str, token: &Token, at: usize) -> Result<Option<Predicate>, QueryError> {
+    let span = token.span_from(at);
+    let Some((operator_at, _)) = text
+        .char_indices()
+        .find(|(_, c)| matches!(c, '<' | '>' | '=' | '!'))
+    else {
+        return Ok(None);
+    };
+    let (name, rest) = text.split_at(operator_at);
+    let (comparison, number) = Comparison::OPERATORS
+        .iter()
+        .find_map(|(op, comparison)| Some((*comparison, rest.strip_prefix(op)?)))
+        .ok_or_else(|| QueryError::new(span.clone(), format!("`{rest}` is not a comparison")))?;
+
+    let name_chars = name.chars().count();
+    let Some(property) = Property::parse(name) else {
+        let name_span = token.span_of(at, name_chars.max(1));
+        return Err(QueryError::new(
+            name_span,
+            format!("can't compare `{name}`, expected {PROPERTIES}"),
+        ));
+    };
+
+    let operator = &rest[..rest.len() - number.len()];
+    let number_span = token.span_from(at + name_chars + operator.chars().count());
+    match number.trim_end_matches('%').parse::<f64>() {
+        Ok(value) => Ok(Some(Predicate::Compare(property, comparison, value))),
+        Err(_) if number.is_empty() => Err(QueryError::new(
+            span,
+            format!("expected a number after `{name}{operator}`"),
+        )),
+        Err(_) => Err(QueryError::new(
+            number_span,
+            format!("expected a number after `{name}{operator}`, found `{number}`"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<Query, QueryError> {
+        Query::parse(query, &SavedQueries::default())
+    }
+
+    /// The part of `query` the error for it points at.
+    fn underlined(query: &str) -> String {
+        let err = parse(query).expect_err("the query should not parse");
+        query
+            .chars()
+            .skip(err.span.start)
+            .take(err.span.len())
+            .collect()
+    }
+
+    fn term(negated: bool, predicate: Predicate) -> Term {
+        Term { negated, predicate }
+    }
+
+    #[test]
+    fn parses_terms() {
+        let query = parse(r#"glass:martini -has:"lime juice" abv>=20 daiq sort:-brix"#).unwrap();
+        assert_eq!(
+            query.terms,
+            [
+                term(false, Predicate::Glass(Glassware::Martini)),
+                term(true, Predicate::Has("lime juice".to_string())),
+                term(
+                    false,
+                    Predicate::Compare(Property::Abv, Comparison::GreaterOrEqual, 20.0)
+                ),
+                term(false, Predicate::Text("daiq".to_string())),
+            ]
+        );
+        assert_eq!(
+            query.sort,
+            Some(Sort {
+                key: SortKey::Property(Property::Brix),
+                descending: true,
+            })
+        );
+    }
+
+    #[test]
+    fn expands_saved_queries() {
+        let mut saved = SavedQueries::default();
+        saved
+            .queries
+            .insert("sours".to_string(), "family:sour sort:abv".to_string());
+        let query = Query::parse("@sours name:d", &saved).unwrap();
+        assert_eq!(
+            query.terms,
+            [
+                term(false, Predicate::Family(Family::Sour)),
+                term(false, Predicate::Name("d".to_string())),
+            ]
+        );
+        assert_eq!(
+            query.sort.map(|sort| sort.key),
+            Some(SortKey::Property(Property::Abv))
+        );
+
+        saved
+            .queries
+            .insert("loop".to_string(), "@loop".to_string());
+        assert!(Query::parse("@loop", &saved).is_err());
+    }
+
+    #[test]
+    fn points_at_unknown_fields() {
+        assert_eq!(underlined("glass:martini colour:red"), "colour");
+        assert_eq!(underlined(r#""colour":red"#), r#""colour""#);
+        assert_eq!(underlined("-colour:red"), "colour");
+        assert_eq!(underlined("abv:20"), "abv");
+        assert_eq!(underlined("sweetness>3"), "sweetness");
+    }
+
+    #[test]
+    fn points_at_bad_values() {
+        assert_eq!(underlined("glass:mug"), "mug");
+        assert_eq!(underlined("sort:colour"), "colour");
+        assert_eq!(underlined("sort:-colour"), "colour");
+        assert_eq!(underlined("abv>lots"), "lots");
+    }
+
+    #[test]
+    fn points_at_quoted_values_with_their_quotes() {
+        assert_eq!(underlined(r#"glass:"beer mug""#), r#""beer mug""#);
+        assert_eq!(
+            underlined(r#"has:lime glass:"beer mug" abv>5"#),
+            r#""beer mug""#
+        );
+        assert_eq!(underlined(r#"-family:"tiki thing""#), r#""tiki thing""#);
+        assert_eq!(underlined(r#"sort:"sweet ness""#), r#""sweet ness""#);
+        assert_eq!(underlined(r#"abv>"a lot""#), r#""a lot""#);
+    }
+
+    #[test]
+    fn points_at_unterminated_quotes() {
+        assert_eq!(underlined(r#"has:"lime juice"#), r#""lime juice"#);
+    }
+}
@@ -3,7 +3,7 @@ use chrono::NaiveDate;
 use measurements::Volume;
 use serde::{Deserialize, Serialize};
 
-use crate::sys::{data::DumbRecipe, glass::Glassware};
+use crate::sys::{data::DumbRecipe, family::Family, glass::Glassware};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Ingredient {
@@ -38,6 +38,14 @@ pub struct Product {
     pub description: Option<String>,
     #[serde(default)]
     pub datasheet: Datasheet,
+    #[serde(default)]
+    pub photo: Option<String>,
+    /// What a litre costs the bar.
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// How much the bar had at the last count.
+    #[serde(default)]
+    pub stock: Option<Volume>,
 }
 
 #[builder]
@@ -58,6 +66,14 @@ pub struct Datasheet {
     #[builder(default)]
     #[serde(default)]
     acidity: f64,
+    #[serde(default)]
+    color: Option<[u8; 3]>,
+}
+
+impl Datasheet {
+    pub fn color(&self) -> Option<[u8; 3]> {
+        self.color
+    }
 }
 
 pub fn abv_to_abw(abv: f64) -> f64 {
@@ -95,7 +111,7 @@ pub enum Quantity {
 }
 
 #[builder]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Recipe {
     pub name: String,
 
@@ -115,6 +131,24 @@ pub struct Recipe {
 
     #[serde(default)]
     pub glassware: Option<Glassware>,
+
+    /// The house glass it is served in, by name.
+    #[serde(default)]
+    pub glass: Option<String>,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub layered: bool,
+
+    #[serde(default)]
+    #[builder(default)]
+    pub on_the_rocks: bool,
+
+    #[serde(default)]
+    pub photo: Option<String>,
+
+    #[serde(default)]
+    pub family: Option<Family>,
 }
 
 impl Recipe {
@@ -130,6 +164,11 @@ impl Recipe {
             ingredients,
             dilution,
             glassware,
+            glass,
+            layered,
+            on_the_rocks,
+            photo,
+            family,
         } = self;
 
         let ingredients = ingredients
@@ -144,6 +183,11 @@ impl Recipe {
             ingredients,
             dilution,
             glassware,
+            glass,
+            layered,
+            on_the_rocks,
+            photo,
+            family,
         }
     }
 }
@@ -169,6 +213,9 @@ impl Preperation {
     }
 }
 
+/// Room taken up by the ice in a drink served on the rocks.
+pub const ICE_DISPLACEMENT: f64 = 60.0;
+
 impl Recipe {
     pub fn calc_volume(&self) -> Volume {
         // Note; There is some volume change when mixing different abv
@@ -180,6 +227,28 @@ impl Recipe {
         Volume::from_milliliters(milis) * ((self.dilution / 100.0) + 1.0)
     }
 
+    /// Room the drink takes up in the glass, including any ice.
+    pub fn calc_serve_volume(&self) -> Volume {
+        let ice = if self.on_the_rocks {
+            ICE_DISPLACEMENT
+        } else {
+            0.0
+        };
+        self.calc_volume() + Volume::from_milliliters(ice)
+    }
+
+    /// The ingredient bringing the most alcohol to the drink.
+    pub fn base_spirit(&self) -> Option<&Product> {
+        let alcohol = |(volume, product): &&(Volume, Product)| {
+            volume.as_milliliters() * product.datasheet.abv
+        };
+        self.ingredients
+            .iter()
+            .filter(|(_, product)| product.datasheet.abv > 0.0)
+            .max_by(|a, b| alcohol(a).total_cmp(&alcohol(b)))
+            .map(|(_, product)| product)
+    }
+
     pub fn calc_abv(&self) -> f64 {
         // https://jeffreymorgenthaler.com/cocktail-abv-calculator/
         let milis = self
@@ -200,4 +269,31 @@ impl Recipe {
         let sugar_in_solution = Volume::from_milliliters(milis);
         sugar_in_solution / self.calc_volume()
     }
+
+    /// Colour of the mixed drink, blending the coloured ingredients by volume.
+    ///
+    /// Ingredients without a colour are left out, so `None` means nothing in
+    /// the recipe has one.
+    pub fn calc_color(&self) -> Option<[u8; 3]> {
+        let coloured: Vec<_> = self
+            .ingredients
+            .iter()
+            .filter_map(|(volume, ingredient)| {
+                Some((volume.as_milliliters(), ingredient.datasheet.color?))
+            })
+            .collect();
+        let total: f64 = coloured.iter().map(|(milis, _)| milis).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let channel = |i: usize| {
+            let sum: f64 = coloured
+                .iter()
+                .map(|(milis, color)| milis * f64::from(color[i]))
+                .sum();
+            (sum / total).round() as u8
+        };
+        Some([channel(0), channel(1), channel(2)])
+    }
 }
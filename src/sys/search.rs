@@ -0,0 +1,196 @@
+use std::fmt;
+
+use crate::sys::{
+    glass::Glassware,
+    recipe::{Product, Recipe},
+};
+
+/// A fuzzy match of a pattern in some text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Char indices into the text that matched the pattern.
+    pub indices: Vec<usize>,
+}
+
+/// Match `pattern` as a case-insensitive subsequence of `text`.
+///
+/// Consecutive characters and characters at the start of a word score
+/// higher, gaps between them cost a little.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            indices: Vec::new(),
+        });
+    }
+
+    let mut indices: Vec<usize> = Vec::with_capacity(pattern.len());
+    let mut score: i64 = 0;
+    let mut wanted = pattern.iter().peekable();
+    let mut previous: Option<char> = None;
+    for (i, c) in text.chars().enumerate() {
+        let Some(&&next) = wanted.peek() else {
+            break;
+        };
+        if c.to_lowercase().eq(std::iter::once(next)) {
+            score += 1;
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 8;
+            }
+            match indices.last() {
+                Some(&last) if last + 1 == i => score += 5,
+                Some(&last) => score -= (i - last - 1).min(3) as i64,
+                None => {}
+            }
+            indices.push(i);
+            wanted.next();
+        }
+        previous = Some(c);
+    }
+
+    (indices.len() == pattern.len()).then_some(FuzzyMatch { score, indices })
+}
+
+/// Find the one value in `all` that `text` names, ignoring case, spaces and
+/// punctuation. Both the display name and the variant name count, and the
+/// start of a name is enough, as long as it's the start of only one.
+pub fn parse_name<T>(all: &[T], text: &str) -> Option<T>
+where
+    T: Copy + fmt::Display + fmt::Debug,
+{
+    let simplify = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let wanted = simplify(text);
+    if wanted.is_empty() {
+        return None;
+    }
+    let names = |value: &T| {
+        [
+            simplify(&value.to_string()),
+            simplify(&format!("{value:?}")),
+        ]
+    };
+
+    if let Some(exact) = all.iter().find(|value| names(value).contains(&wanted)) {
+        return Some(*exact);
+    }
+    let mut candidates = all
+        .iter()
+        .filter(|value| names(value).iter().any(|name| name.starts_with(&wanted)));
+    match (candidates.next(), candidates.next()) {
+        (Some(value), None) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Structured filters, all of which a recipe must pass.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub glassware: Option<Glassware>,
+    pub base_spirit: Option<String>,
+    pub ingredient: Option<String>,
+    pub abv_min: Option<f64>,
+    pub abv_max: Option<f64>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        *self == Filter::default()
+    }
+
+    pub fn matches(&self, recipe: &Recipe) -> bool {
+        let contains = |product: &Product, name: &str| {
+            product.name.to_lowercase().contains(&name.to_lowercase())
+        };
+
+        if self.glassware.is_some() && recipe.glassware != self.glassware {
+            return false;
+        }
+        if let Some(spirit) = &self.base_spirit {
+            if !recipe
+                .base_spirit()
+                .is_some_and(|base| contains(base, spirit))
+            {
+                return false;
+            }
+        }
+        if let Some(ingredient) = &self.ingredient {
+            if !recipe
+                .ingredients
+                .iter()
+                .any(|(_, p)| contains(p, ingredient))
+            {
+                return false;
+            }
+        }
+
+        let abv = recipe.calc_abv();
+        self.abv_min.is_none_or(|min| abv >= min) && self.abv_max.is_none_or(|max| abv <= max)
+    }
+
+    /// Parse a glass name, see [`parse_name`].
+    pub fn parse_glassware(text: &str) -> Option<Glassware> {
+        parse_name(&Glassware::ALL, text)
+    }
+
+    /// Parse an ABV range like `10-25`, `20-` or `-15`, or a single value for
+    /// an exact match.
+    pub fn parse_abv(text: &str) -> Option<(Option<f64>, Option<f64>)> {
+        let bound = |s: &str| -> Option<Option<f64>> {
+            let s = s.trim().trim_end_matches('%');
+            if s.is_empty() {
+                Some(None)
+            } else {
+                s.parse().ok().map(Some)
+            }
+        };
+        match text.split_once('-') {
+            Some((min, max)) => Some((bound(min)?, bound(max)?)),
+            None => {
+                let exact = bound(text)?;
+                Some((exact, exact))
+            }
+        }
+    }
+}
+
+/// A recipe that made it through the search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub name: String,
+    pub score: i64,
+    /// Char indices into `name` to highlight.
+    pub highlight: Vec<usize>,
+}
+
+/// Fuzzy match `query` against the name, descriptions and ingredient names
+/// of a recipe.
+///
+/// Returns the score and the char indices of `name` to highlight.
+pub fn text_match(query: &str, name: &str, recipe: &Recipe) -> Option<(i64, Vec<usize>)> {
+    let by_name = fuzzy_match(query, name);
+    let elsewhere = [recipe.short_desc.as_deref(), recipe.description.as_deref()]
+        .into_iter()
+        .flatten()
+        .chain(recipe.ingredients.iter().map(|(_, p)| p.name.as_str()))
+        .filter_map(|text| fuzzy_match(query, text))
+        .map(|m| m.score)
+        .max();
+
+    // Matching the name counts for more than matching the small print.
+    match (by_name, elsewhere) {
+        (Some(m), _) => Some((m.score * 2, m.indices)),
+        (None, Some(score)) => Some((score, Vec::new())),
+        (None, None) => None,
+    }
+}
@@ -1,15 +1,70 @@
-use std::{iter, num::Wrapping};
-
-use color_eyre::owo_colors::{colors::css::Teal, OwoColorize};
 use ratatui::{
     prelude::*,
     widgets::{List, ListItem, Paragraph},
 };
 
-use crate::sys::{data::DumbRecipe, recipe::Recipe};
+use crate::{
+    sys::{
+        data::{DumbRecipe, GlassCheck, Tally},
+        glass::Fit,
+    },
+    ui::theme::Theme,
+};
 
 pub struct RecipeCard<'a> {
     pub recipe: Option<&'a DumbRecipe>,
+    pub glass_check: Option<GlassCheck>,
+    /// How the recipe does behind the bar, for those who work there.
+    pub tally: Option<Tally>,
+    pub theme: &'a Theme,
+    /// Lines of the description scrolled past.
+    pub scroll: u16,
+}
+
+impl RecipeCard<'_> {
+    fn glass_warning(&self) -> Option<Line<'static>> {
+        let check = self.glass_check.as_ref()?;
+        let glass = &check.glass;
+
+        let volume = check.volume.as_milliliters().round();
+        let capacity = check.capacity.as_milliliters().round();
+        let warning = match check.fit {
+            Fit::Good => return None,
+            Fit::Overflowing => Span::styled(
+                format!("{volume} ml won't fit a {glass} ({capacity} ml)"),
+                self.theme.fg(self.theme.error),
+            ),
+            Fit::Underfilled => Span::styled(
+                format!("{volume} ml looks lost in a {glass} ({capacity} ml)"),
+                self.theme.fg(self.theme.warning),
+            ),
+        };
+
+        let mut line = Line::from(warning);
+        if let Some(suggestion) = &check.suggestion {
+            line.push_span(Span::from(format!(", try a {suggestion}")).italic());
+        }
+        Some(line.centered())
+    }
+
+    /// A line like `12 poured · 1.85 a drink · enough for 30 more`.
+    fn tally_line(&self) -> Option<Line<'static>> {
+        let tally = self.tally.as_ref()?;
+        let mut parts = Vec::new();
+        if tally.poured > 0 {
+            parts.push(format!("{} poured", tally.poured));
+        }
+        if let Some(cost) = tally.cost {
+            parts.push(format!("{cost:.2} a drink"));
+        }
+        if let Some(left) = tally.left {
+            parts.push(format!("enough for {left} more"));
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        Some(Line::styled(parts.join(" · "), self.theme.fg(self.theme.muted)).centered())
+    }
 }
 
 impl<'a> Widget for &RecipeCard<'a> {
@@ -18,18 +73,24 @@ impl<'a> Widget for &RecipeCard<'a> {
         Self: Sized,
     {
         if let Some(recipe) = self.recipe {
-            let [top, short, mid, bottom] = Layout::new(
+            let warning = self.glass_warning();
+            let tally = self.tally_line();
+            let [top, short, bar, fit, mid, bottom] = Layout::new(
                 Direction::Vertical,
                 [
                     Constraint::Length(1),
                     Constraint::Max(3),
+                    Constraint::Length(if tally.is_some() { 1 } else { 0 }),
+                    Constraint::Length(if warning.is_some() { 2 } else { 0 }),
                     Constraint::Min(4),
                     Constraint::Fill(1),
                 ],
             )
             .areas(area);
 
-            let cocktail_name = Span::from(&recipe.name).blue().bold().into_centered_line();
+            let cocktail_name = Span::styled(&recipe.name, self.theme.fg(self.theme.recipe_name))
+                .bold()
+                .into_centered_line();
             cocktail_name.render(top, buf);
 
             if let Some(short_desc) = recipe.short_desc.as_deref() {
@@ -40,6 +101,16 @@ impl<'a> Widget for &RecipeCard<'a> {
                     .render(short, buf);
             }
 
+            if let Some(tally) = tally {
+                tally.render(bar, buf);
+            }
+
+            if let Some(warning) = warning {
+                Paragraph::new(warning)
+                    .wrap(ratatui::widgets::Wrap { trim: true })
+                    .render(fit, buf);
+            }
+
             // list
 
             let [heading, mid] = Layout::default()
@@ -72,6 +143,7 @@ impl<'a> Widget for &RecipeCard<'a> {
                     .render(heading, buf);
                 Paragraph::new(desc)
                     .wrap(ratatui::widgets::Wrap { trim: true })
+                    .scroll((self.scroll, 0))
                     .render(bottom, buf);
             }
         } else {
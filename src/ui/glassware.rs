@@ -1,24 +1,39 @@
+use std::f64::consts::PI;
+
+use measurements::Volume;
 use ratatui::{
     prelude::*,
+    symbols::Marker,
     widgets::{
-        canvas::{self, Context, Line, Rectangle, Shape},
-        Block, Widget,
+        canvas::{Canvas, Context, Line, Rectangle},
+        Widget,
     },
 };
 
-use crate::sys::glass::Glassware;
+use crate::{
+    sys::{
+        glass::Glassware,
+        recipe::{Recipe, ICE_DISPLACEMENT},
+    },
+    ui::theme::Theme,
+};
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Glass {
     pub kind: Glassware,
-    pub filled: bool,
+    pub filled: Option<Liquid>,
+    /// Real capacity of the glass, if it differs from the nominal one.
+    pub capacity: Option<Volume>,
+    pub theme: Theme,
 }
 
 impl From<Glassware> for Glass {
     fn from(value: Glassware) -> Self {
         Glass {
             kind: value,
-            filled: false,
+            filled: None,
+            capacity: None,
+            theme: Theme::default(),
         }
     }
 }
@@ -27,7 +42,33 @@ impl Glass {
     pub fn new() -> Self {
         Glass {
             kind: Glassware::Martini,
-            filled: true,
+            filled: None,
+            capacity: None,
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn filled(self, recipe: &Recipe) -> Self {
+        Glass {
+            filled: Some(Liquid::from(recipe)),
+            ..self
+        }
+    }
+
+    pub fn silhouette(&self) -> Silhouette {
+        match self.kind {
+            Glassware::Lowball => Tumbler::lowball().silhouette(),
+            Glassware::Highball => Tumbler::highball().silhouette(),
+            Glassware::Collins => Tumbler::collins().silhouette(),
+            Glassware::JulepCup => Tumbler::julep_cup().silhouette(),
+            Glassware::Martini => Stemmed::martini().silhouette(),
+            Glassware::NickAndNora => Stemmed::nick_and_nora().silhouette(),
+            Glassware::Coupe => Stemmed::coupe().silhouette(),
+            Glassware::Flute => Stemmed::flute().silhouette(),
+            Glassware::Wine => Stemmed::wine().silhouette(),
+            Glassware::Snifter => Stemmed::snifter().silhouette(),
+            Glassware::Hurricane => Stemmed::hurricane().silhouette(),
+            Glassware::Tiki => Tiki::new().silhouette(),
         }
     }
 }
@@ -43,161 +84,644 @@ impl Widget for Glass {
     where
         Self: Sized,
     {
-        //let tumbler = Tumbler { width: 80.0, height: 40.0 };
-
-        canvas::Canvas::default()
-            .x_bounds([-80.0, 80.0])
-            .y_bounds([-80.0, 80.0])
-            .paint(|ctx| match self.kind {
-                Glassware::Martini => {
-                    let martini = Martini::new();
-                    martini.draw(ctx, Color::White);
-                }
-                Glassware::Lowball => {
-                    let tumbler = Martini::new();
-                    tumbler.draw(ctx, Color::White);
+        let silhouette = self.silhouette();
+        let (x_bounds, y_bounds) = fit_bounds(area, silhouette.width(), silhouette.height());
+        // One braille dot row, so the liquid is drawn without gaps.
+        let step = (y_bounds[1] - y_bounds[0]) / (f64::from(area.height.max(1)) * 4.0);
+        // The drawing holds its own amount, so pour in the same share of it
+        // as the drink takes up of the real glass.
+        let capacity = self.capacity.unwrap_or_else(|| self.kind.capacity());
+        let scale = silhouette.capacity() / capacity.as_milliliters();
+        let theme = &self.theme;
+        let surface = self
+            .filled
+            .as_ref()
+            .map(|liquid| liquid.surface(&silhouette, scale));
+
+        if theme.ascii {
+            let bounds = (x_bounds, y_bounds);
+            if let Some(liquid) = &self.filled {
+                let pour =
+                    |ctx: &mut Context<'_>| liquid.draw(ctx, &silhouette, scale, step, theme);
+                stamp(bounds, pour, "~", area, buf);
+                if let Some(top) = surface.filter(|_| liquid.ice) {
+                    let ice = |ctx: &mut Context<'_>| draw_ice(ctx, &silhouette, top, theme.ice);
+                    stamp(bounds, ice, "o", area, buf);
                 }
-                _ => {
-                    ctx.print(0., 0., "Unknown");
+            }
+            let outline = |ctx: &mut Context<'_>| silhouette.draw(ctx, theme.glass);
+            stamp(bounds, outline, "#", area, buf);
+            return;
+        }
+
+        Canvas::default()
+            .x_bounds(x_bounds)
+            .y_bounds(y_bounds)
+            .paint(|ctx| {
+                if let Some(liquid) = &self.filled {
+                    liquid.draw(ctx, &silhouette, scale, step, theme);
+                    ctx.layer();
+                    if let Some(top) = surface.filter(|_| liquid.ice) {
+                        draw_ice(ctx, &silhouette, top, theme.ice);
+                        ctx.layer();
+                    }
                 }
+                silhouette.draw(ctx, theme.glass);
             })
             .render(area, buf)
     }
 }
 
-pub struct Martini {
-    head_height: f64,
-    head_width: f64,
-    stilk_height: f64,
-    stilk_width: f64,
-    foot_height: f64,
-    foot_width: f64,
+/// Paint on a canvas of its own, one dot per cell, and copy every cell it
+/// drew on into `buf` as `symbol`, keeping the colour it was drawn in.
+fn stamp<F>(bounds: ([f64; 2], [f64; 2]), paint: F, symbol: &str, area: Rect, buf: &mut Buffer)
+where
+    F: Fn(&mut Context<'_>),
+{
+    let mut scratch = Buffer::empty(area);
+    Canvas::default()
+        .marker(Marker::Dot)
+        .x_bounds(bounds.0)
+        .y_bounds(bounds.1)
+        .paint(paint)
+        .render(area, &mut scratch);
+    for position in area.positions() {
+        let (Some(drawn), Some(cell)) = (scratch.cell(position), buf.cell_mut(position)) else {
+            continue;
+        };
+        if drawn.symbol() != " " {
+            cell.set_symbol(symbol).set_fg(drawn.fg);
+        }
+    }
 }
 
-impl Martini {
-    pub fn new() -> Self {
-        Martini {
-            head_width: 40.0,
-            head_height: 35.0,
-            stilk_height: 20.0,
-            foot_height: 4.0,
-            foot_width: 30.0,
-            stilk_width: 2.0,
+/// What is poured into a glass, bottom layer first.
+#[derive(Clone, Debug)]
+pub struct Liquid {
+    /// Millilitres and colour of each layer, `None` for a clear one.
+    pub layers: Vec<(f64, Option<Color>)>,
+    pub ice: bool,
+}
+
+impl From<&Recipe> for Liquid {
+    fn from(recipe: &Recipe) -> Self {
+        let to_color = |color: Option<[u8; 3]>| color.map(|[r, g, b]| Color::Rgb(r, g, b));
+
+        let layers = if recipe.layered {
+            let dilution = (recipe.dilution / 100.0) + 1.0;
+            recipe
+                .ingredients
+                .iter()
+                .map(|(volume, product)| {
+                    let milis = volume.as_milliliters() * dilution;
+                    (milis, to_color(product.datasheet.color()))
+                })
+                .collect()
+        } else {
+            let milis = recipe.calc_volume().as_milliliters();
+            vec![(milis, to_color(recipe.calc_color()))]
+        };
+
+        Liquid {
+            layers,
+            ice: recipe.on_the_rocks,
         }
     }
 }
 
-impl Default for Martini {
-    fn default() -> Self {
-        Self::new()
+impl Liquid {
+    /// Ice pushes every layer up by its share of the drink.
+    fn rise(&self) -> f64 {
+        let total: f64 = self.layers.iter().map(|(milis, _)| milis).sum();
+        if self.ice && total > 0.0 {
+            (total + ICE_DISPLACEMENT) / total
+        } else {
+            1.0
+        }
+    }
+
+    /// Height of the surface in `silhouette`, `scale`d like in [`Liquid::draw`].
+    fn surface(&self, silhouette: &Silhouette, scale: f64) -> f64 {
+        let total: f64 = self.layers.iter().map(|(milis, _)| milis).sum();
+        silhouette.level(total * self.rise() * scale)
+    }
+
+    /// Fill `silhouette` with horizontal lines `step` apart, each layer
+    /// stacked on the one below and `scale`d to the size of the drawing.
+    fn draw(
+        &self,
+        ctx: &mut Context<'_>,
+        silhouette: &Silhouette,
+        scale: f64,
+        step: f64,
+        theme: &Theme,
+    ) {
+        let rise = self.rise();
+        let mut poured = 0.0;
+        let mut y = silhouette.floor;
+        for &(milis, color) in &self.layers {
+            let color = color.map_or(theme.clear_liquid, |color| theme.adapt(color));
+            poured += milis * rise * scale;
+            let top = silhouette.level(poured);
+            while y < top {
+                let r = silhouette.inner_radius(y);
+                ctx.draw(&Line {
+                    x1: -r,
+                    y1: y,
+                    x2: r,
+                    y2: y,
+                    color,
+                });
+                y += step;
+            }
+        }
     }
 }
 
-impl Martini {
-    pub fn draw(&self, ctx: &mut Context<'_>, color: Color) {
-        ctx.draw(&Line {
-            x1: -self.stilk_width / 2.0,
-            y1: 0.0,
-            x2: -self.stilk_width / 2.0,
-            y2: self.stilk_height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: self.stilk_width / 2.0,
-            y1: 0.0,
-            x2: self.stilk_width / 2.0,
-            y2: self.stilk_height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: self.stilk_width / 2.0,
-            y1: self.stilk_height,
-            x2: self.head_width,
-            y2: self.head_height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: -self.stilk_width / 2.0,
-            y1: self.stilk_height,
-            x2: -self.head_width,
-            y2: self.head_height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: self.head_width,
-            y1: self.head_height,
-            x2: -self.head_width,
-            y2: self.head_height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: -self.stilk_width / 2.0,
-            y1: 0.0,
-            x2: -self.foot_width,
-            y2: -self.foot_height / 2.0,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: self.stilk_width / 2.0,
-            y1: 0.0,
-            x2: self.foot_width,
-            y2: -self.foot_height / 2.0,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: -self.foot_width,
-            y1: -self.foot_height / 2.0,
-            x2: self.foot_width,
-            y2: -self.foot_height / 2.0,
-            color,
-        });
-    }
-}
-
-struct Tumbler {
-    width: f64,
-    height: f64,
+/// Stack rows of ice cubes from the floor of the glass up to `top`.
+fn draw_ice(ctx: &mut Context<'_>, silhouette: &Silhouette, top: f64, color: Color) {
+    const SIDE: f64 = 18.0;
+    const GAP: f64 = 3.0;
+
+    let mut y = silhouette.floor + GAP;
+    let mut row = 0;
+    while y + SIDE / 2.0 < top {
+        let room = silhouette
+            .inner_radius(y)
+            .min(silhouette.inner_radius(y + SIDE));
+        let count = ((2.0 * room - GAP) / (SIDE + GAP)).floor();
+        if count < 1.0 {
+            // Too narrow down here, cubes settle higher up.
+            y += GAP;
+            continue;
+        }
+        // Stagger every other row, like cubes settling on each other.
+        let shift = if row % 2 == 0 { 0.0 } else { GAP };
+        let start = -(count * (SIDE + GAP) - GAP) / 2.0 + shift;
+        for i in 0..count as usize {
+            ctx.draw(&Rectangle {
+                x: start + i as f64 * (SIDE + GAP),
+                y,
+                width: SIDE,
+                height: SIDE,
+                color,
+            });
+        }
+        y += SIDE + GAP;
+        row += 1;
+    }
 }
 
-impl Tumbler {
+/// Canvas bounds that fit a `width` x `height` drawing into `area` without distorting it.
+///
+/// A terminal cell is roughly twice as tall as it is wide, so the vertical
+/// extent of the area counts double.
+fn fit_bounds(area: Rect, width: f64, height: f64) -> ([f64; 2], [f64; 2]) {
+    const MARGIN: f64 = 1.1;
+    let cols = f64::from(area.width.max(1));
+    let rows = f64::from(area.height.max(1)) * 2.0;
+
+    let scale = (width * MARGIN / cols).max(height * MARGIN / rows);
+    let (span_x, span_y) = (cols * scale, rows * scale);
+    let bottom = (height - span_y) / 2.0;
+
+    ([-span_x / 2.0, span_x / 2.0], [bottom, bottom + span_y])
+}
+
+/// Outline of a glass, measured in millimetres.
+///
+/// `outline` is the right half of the glass, from the outer edge of the foot
+/// up to the rim. It is mirrored around `x = 0` when drawn, and the foot and
+/// rim are closed off with straight lines. `details` are extra line segments
+/// drawn as-is, like the thick base of a tumbler. `floor` is the height of the
+/// inside bottom of the glass, where the liquid starts.
+pub struct Silhouette {
+    pub outline: Vec<(f64, f64)>,
+    pub details: Vec<[f64; 4]>,
+    pub floor: f64,
+}
+
+impl Silhouette {
+    pub fn width(&self) -> f64 {
+        2.0 * self.outline.iter().map(|&(x, _)| x).fold(0.0, f64::max)
+    }
+
+    pub fn height(&self) -> f64 {
+        self.outline.iter().map(|&(_, y)| y).fold(0.0, f64::max)
+    }
+
+    /// Radius of the inside of the glass at height `y`.
+    pub fn inner_radius(&self, y: f64) -> f64 {
+        const WALL: f64 = 2.0;
+        self.outline
+            .windows(2)
+            .rev()
+            .find_map(|pair| {
+                let [(x1, y1), (x2, y2)] = [pair[0], pair[1]];
+                (y1 < y2 && (y1..=y2).contains(&y)).then(|| x1 + (x2 - x1) * (y - y1) / (y2 - y1))
+            })
+            .map_or(0.0, |x| (x - WALL).max(0.0))
+    }
+
+    /// Millilitres held between the floor and height `y`.
+    pub fn volume_below(&self, y: f64) -> f64 {
+        const SLICE: f64 = 0.5;
+        let mut cubic_mm = 0.0;
+        let mut h = self.floor;
+        while h < y {
+            let dy = SLICE.min(y - h);
+            let r = self.inner_radius(h + dy / 2.0);
+            cubic_mm += PI * r * r * dy;
+            h += dy;
+        }
+        cubic_mm / 1000.0
+    }
+
+    /// Millilitres the glass holds when filled to the rim.
+    pub fn capacity(&self) -> f64 {
+        self.volume_below(self.height())
+    }
+
+    /// Height the surface reaches when the glass holds `milis` millilitres,
+    /// stopping at the rim.
+    pub fn level(&self, milis: f64) -> f64 {
+        let (mut low, mut high) = (self.floor, self.height());
+        if milis >= self.capacity() {
+            return high;
+        }
+        for _ in 0..32 {
+            let mid = (low + high) / 2.0;
+            if self.volume_below(mid) < milis {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
     pub fn draw(&self, ctx: &mut Context<'_>, color: Color) {
-        ctx.draw(&Line {
-            x1: -self.width / 2.0,
-            y1: 0.0,
-            x2: -self.width / 2.0,
-            y2: self.height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: self.width / 2.0,
-            y1: 0.0,
-            x2: self.width / 2.0,
-            y2: self.height,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: -self.width / 2.0,
-            y1: 0.0,
-            x2: self.width / 2.0,
-            y2: 0.0,
-            color,
-        });
-        ctx.draw(&Line {
-            x1: -self.width / 2.0,
-            y1: self.height,
-            x2: self.width / 2.0,
-            y2: self.height,
-            color,
-        });
-
-        for x in (1..7).map(|i| (self.width / 7.0) * i as f64) {
+        for pair in self.outline.windows(2) {
+            let [(x1, y1), (x2, y2)] = [pair[0], pair[1]];
+            ctx.draw(&Line {
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+            });
+            ctx.draw(&Line {
+                x1: -x1,
+                y1,
+                x2: -x2,
+                y2,
+                color,
+            });
+        }
+
+        let ends = [self.outline.first(), self.outline.last()];
+        for &(x, y) in ends.into_iter().flatten() {
+            ctx.draw(&Line {
+                x1: -x,
+                y1: y,
+                x2: x,
+                y2: y,
+                color,
+            });
+        }
+
+        for &[x1, y1, x2, y2] in &self.details {
             ctx.draw(&Line {
-                x1: -self.width / 2.0 + x,
-                y1: 0.0,
-                x2: -self.width / 2.0 + x,
-                y2: self.height * 0.2,
+                x1,
+                y1,
+                x2,
+                y2,
                 color,
             });
         }
     }
 }
+
+/// Sample `f` at `steps + 1` evenly spaced points in `0.0..=1.0`.
+fn curve(steps: usize, f: impl Fn(f64) -> (f64, f64)) -> impl Iterator<Item = (f64, f64)> {
+    (0..=steps).map(move |i| f(i as f64 / steps as f64))
+}
+
+/// Straight sided glasses without a stem.
+pub struct Tumbler {
+    bottom_width: f64,
+    top_width: f64,
+    height: f64,
+    base: f64,
+    rim_band: Option<f64>,
+}
+
+impl Tumbler {
+    pub fn lowball() -> Self {
+        Tumbler {
+            bottom_width: 80.0,
+            top_width: 84.0,
+            height: 90.0,
+            base: 12.0,
+            rim_band: None,
+        }
+    }
+
+    pub fn highball() -> Self {
+        Tumbler {
+            bottom_width: 64.0,
+            top_width: 68.0,
+            height: 140.0,
+            base: 10.0,
+            rim_band: None,
+        }
+    }
+
+    pub fn collins() -> Self {
+        Tumbler {
+            bottom_width: 58.0,
+            top_width: 58.0,
+            height: 165.0,
+            base: 10.0,
+            rim_band: None,
+        }
+    }
+
+    pub fn julep_cup() -> Self {
+        Tumbler {
+            bottom_width: 70.0,
+            top_width: 82.0,
+            height: 95.0,
+            base: 3.0,
+            rim_band: Some(6.0),
+        }
+    }
+
+    pub fn silhouette(&self) -> Silhouette {
+        let bottom = self.bottom_width / 2.0;
+        let top = self.top_width / 2.0;
+        let at = |y: f64| bottom + (top - bottom) * y / self.height;
+
+        let mut details = vec![[-at(self.base), self.base, at(self.base), self.base]];
+        if let Some(band) = self.rim_band {
+            let y = self.height - band;
+            details.push([-at(y), y, at(y), y]);
+        }
+
+        Silhouette {
+            outline: vec![(bottom, 0.0), (top, self.height)],
+            details,
+            floor: self.base,
+        }
+    }
+}
+
+/// The bowl sitting on top of a stem.
+pub enum Bowl {
+    /// Straight walls flaring out to the rim, like a martini glass.
+    Cone { width: f64, depth: f64 },
+    /// A rounded cup, like a coupe.
+    Round { width: f64, depth: f64 },
+    /// Widening to `belly` and closing in again towards the rim.
+    Tulip { belly: f64, width: f64, depth: f64 },
+    /// Bulging at the bottom, pinched at the waist, flaring at the rim.
+    Hurricane {
+        belly: f64,
+        waist: f64,
+        width: f64,
+        depth: f64,
+    },
+}
+
+impl Bowl {
+    fn outline(&self, stem: f64, base: f64) -> Vec<(f64, f64)> {
+        const STEPS: usize = 16;
+        match *self {
+            Bowl::Cone { width, depth } => vec![(stem, base), (width / 2.0, base + depth)],
+            Bowl::Round { width, depth } => curve(STEPS, |t| {
+                let angle = t * PI / 2.0;
+                let x = (width / 2.0 * angle.sin()).max(stem);
+                (x, base + depth * (1.0 - angle.cos()))
+            })
+            .collect(),
+            Bowl::Tulip {
+                belly,
+                width,
+                depth,
+            } => {
+                const WIDEST: f64 = 0.45;
+                curve(STEPS, |t| {
+                    let x = if t <= WIDEST {
+                        belly / 2.0 * (t / WIDEST * PI / 2.0).sin()
+                    } else {
+                        let closing = (t - WIDEST) / (1.0 - WIDEST);
+                        width / 2.0 + (belly - width) / 2.0 * (closing * PI / 2.0).cos()
+                    };
+                    (x.max(stem), base + depth * t)
+                })
+                .collect()
+            }
+            Bowl::Hurricane {
+                belly,
+                waist,
+                width,
+                depth,
+            } => curve(STEPS * 2, |t| {
+                // Blend between the three widths along a cosine, so the
+                // glass curves smoothly through each of them.
+                let x = if t <= 0.25 {
+                    belly / 2.0 * (t / 0.25 * PI / 2.0).sin()
+                } else if t <= 0.65 {
+                    let s = (1.0 - ((t - 0.25) / 0.4 * PI).cos()) / 2.0;
+                    belly / 2.0 + (waist - belly) / 2.0 * s
+                } else {
+                    let s = (1.0 - ((t - 0.65) / 0.35 * PI).cos()) / 2.0;
+                    waist / 2.0 + (width - waist) / 2.0 * s
+                };
+                (x.max(stem), base + depth * t)
+            })
+            .collect(),
+        }
+    }
+}
+
+/// Glasses with a foot, a stem, and a bowl.
+pub struct Stemmed {
+    foot_width: f64,
+    foot_height: f64,
+    stem_width: f64,
+    stem_height: f64,
+    bowl: Bowl,
+}
+
+impl Stemmed {
+    pub fn martini() -> Self {
+        Stemmed {
+            foot_width: 76.0,
+            foot_height: 3.0,
+            stem_width: 5.0,
+            stem_height: 75.0,
+            bowl: Bowl::Cone {
+                width: 110.0,
+                depth: 55.0,
+            },
+        }
+    }
+
+    pub fn nick_and_nora() -> Self {
+        Stemmed {
+            foot_width: 64.0,
+            foot_height: 3.0,
+            stem_width: 5.0,
+            stem_height: 80.0,
+            bowl: Bowl::Round {
+                width: 76.0,
+                depth: 60.0,
+            },
+        }
+    }
+
+    pub fn coupe() -> Self {
+        Stemmed {
+            foot_width: 72.0,
+            foot_height: 3.0,
+            stem_width: 5.0,
+            stem_height: 85.0,
+            bowl: Bowl::Round {
+                width: 110.0,
+                depth: 40.0,
+            },
+        }
+    }
+
+    pub fn flute() -> Self {
+        Stemmed {
+            foot_width: 66.0,
+            foot_height: 3.0,
+            stem_width: 5.0,
+            stem_height: 65.0,
+            bowl: Bowl::Tulip {
+                belly: 56.0,
+                width: 50.0,
+                depth: 125.0,
+            },
+        }
+    }
+
+    pub fn wine() -> Self {
+        Stemmed {
+            foot_width: 76.0,
+            foot_height: 3.0,
+            stem_width: 6.0,
+            stem_height: 70.0,
+            bowl: Bowl::Tulip {
+                belly: 86.0,
+                width: 68.0,
+                depth: 100.0,
+            },
+        }
+    }
+
+    pub fn snifter() -> Self {
+        Stemmed {
+            foot_width: 76.0,
+            foot_height: 4.0,
+            stem_width: 8.0,
+            stem_height: 25.0,
+            bowl: Bowl::Tulip {
+                belly: 100.0,
+                width: 58.0,
+                depth: 90.0,
+            },
+        }
+    }
+
+    pub fn hurricane() -> Self {
+        Stemmed {
+            foot_width: 76.0,
+            foot_height: 5.0,
+            stem_width: 14.0,
+            stem_height: 20.0,
+            bowl: Bowl::Hurricane {
+                belly: 80.0,
+                waist: 56.0,
+                width: 84.0,
+                depth: 175.0,
+            },
+        }
+    }
+
+    pub fn silhouette(&self) -> Silhouette {
+        let stem = self.stem_width / 2.0;
+        let base = self.foot_height + self.stem_height;
+
+        let mut outline = vec![
+            (self.foot_width / 2.0, 0.0),
+            (self.foot_width / 2.0 * 0.9, self.foot_height),
+            (stem, self.foot_height * 2.0),
+        ];
+        outline.extend(self.bowl.outline(stem, base));
+
+        Silhouette {
+            outline,
+            details: Vec::new(),
+            floor: base,
+        }
+    }
+}
+
+/// A tiki mug, slightly barrel shaped with a carved face.
+pub struct Tiki {
+    width: f64,
+    belly: f64,
+    height: f64,
+    base: f64,
+}
+
+impl Tiki {
+    pub fn new() -> Self {
+        Tiki {
+            width: 76.0,
+            belly: 86.0,
+            height: 150.0,
+            base: 8.0,
+        }
+    }
+
+    pub fn silhouette(&self) -> Silhouette {
+        let outline = curve(12, |t| {
+            let bulge = (self.belly - self.width) / 2.0 * (t * PI).sin();
+            (self.width / 2.0 + bulge, self.height * t)
+        })
+        .collect();
+
+        let (w, h) = (self.width / 2.0, self.height);
+        let details = vec![
+            // brow
+            [-w * 0.7, h * 0.75, w * 0.7, h * 0.75],
+            // eyes
+            [-w * 0.55, h * 0.65, -w * 0.2, h * 0.65],
+            [w * 0.2, h * 0.65, w * 0.55, h * 0.65],
+            // nose
+            [0.0, h * 0.7, -w * 0.15, h * 0.45],
+            [-w * 0.15, h * 0.45, w * 0.15, h * 0.45],
+            // mouth
+            [-w * 0.6, h * 0.3, w * 0.6, h * 0.3],
+            [-w * 0.6, h * 0.3, -w * 0.5, h * 0.2],
+            [w * 0.6, h * 0.3, w * 0.5, h * 0.2],
+            [-w * 0.5, h * 0.2, w * 0.5, h * 0.2],
+        ];
+
+        Silhouette {
+            outline,
+            details,
+            floor: self.base,
+        }
+    }
+}
+
+impl Default for Tiki {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,15 +1,25 @@
 pub mod card;
 pub mod glassware;
+pub mod photo;
+pub mod theme;
 
+use chrono::Local;
 use ratatui::{
     prelude::*,
     widgets::{Block, Borders, Clear, HighlightSpacing, List, ListItem, Paragraph, Wrap},
 };
 
 use crate::{
-    app::{App, CurrentMode, CurrentlyEditing},
-    sys::{self, glass::Glassware},
-    ui::card::RecipeCard,
+    app::{
+        history::{Change, Step},
+        role::Role,
+        App, CurrentMode, CurrentScreen, CurrentlyEditing, FilterField, Overlay, Target,
+    },
+    sys::{
+        glass::Glassware,
+        search::{Filter, Hit},
+    },
+    ui::{card::RecipeCard, theme::Theme},
 };
 
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
@@ -45,66 +55,100 @@ pub fn entry(frame: &mut Frame, app: &mut App) {
         ])
         .split(frame.area());
 
-    let title_block = Block::default()
+    // Cloned so the widgets below can still borrow `app` mutably.
+    let theme = &app.theme.clone();
+    let mut title_block = Block::default()
         .borders(Borders::ALL)
         .style(Style::default());
-
-    let title = Paragraph::new(Text::styled(
-        "CALICOMP",
-        Style::default().bold().fg(Color::Green),
-    ))
-    .centered()
-    .block(title_block);
+    let collection = app.current_collection();
+    if app.collections.len() > 1 || collection.role != Role::Manager {
+        let name = format!(" {} as {} ", collection.name, collection.role);
+        title_block = title_block.title(Line::styled(name, theme.fg(theme.muted)).right_aligned());
+    }
+    let title = match &app.banner {
+        Some(banner) => Text::styled(banner.as_str(), theme.fg(theme.warning).bold()),
+        None => Text::styled("CALICOMP", theme.fg(theme.title).bold()),
+    };
+    let title = Paragraph::new(title).centered().block(title_block);
 
     frame.render_widget(title, chunks[0]);
+    app.regions.targets.clear();
+    app.regions.panes = chunks[1];
 
     // Here we go!
     let list = List::from_iter(
-        app.recipes
+        app.visible_recipes()
             .iter()
-            .map(|recipe| ListItem::from(recipe.clone())),
+            .map(|hit| highlighted(hit, theme)),
     )
     .highlight_spacing(HighlightSpacing::Always)
-    .highlight_style(Style::new().yellow());
+    .highlight_style(theme.selection_style());
 
     let [left, right] = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Ratio(1, 2); 2])
+        .constraints([
+            Constraint::Percentage(app.split),
+            Constraint::Percentage(100 - app.split),
+        ])
         .areas(chunks[1]);
 
-    frame.render_stateful_widget(list, left, &mut app.list_state);
+    let searching =
+        matches!(app.current_mode, CurrentMode::Searching) || !app.search_query().trim().is_empty();
+    let [list_area, search_area, filter_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),
+            Constraint::Length(if searching { 3 } else { 0 }),
+            Constraint::Length(if app.filter.is_empty() { 0 } else { 1 }),
+        ])
+        .areas(left);
+
+    frame.render_stateful_widget(list, list_area, &mut app.list_state);
+    app.regions.list = list_area;
+    if searching {
+        search_bar(frame, app, search_area);
+    }
+    if !app.filter.is_empty() {
+        frame.render_widget(filter_summary(&app.filter, &app.theme), filter_area);
+    }
     recipe_window(app, frame, right);
 
     let current_navigation_text = vec![
         // The first half of the text
         match app.current_mode {
-            CurrentMode::Main => Span::styled("Viewing Mode", Style::default().fg(Color::Green)),
-            CurrentMode::Editing => {
-                Span::styled("Editing Mode", Style::default().fg(Color::Yellow))
-            }
-            CurrentMode::Exiting => Span::styled("Exiting", Style::default().fg(Color::LightRed)),
+            CurrentMode::Main => Span::styled("Viewing Mode", theme.fg(theme.viewing)),
+            CurrentMode::Editing => Span::styled("Editing Mode", theme.fg(theme.editing)),
+            CurrentMode::Searching => Span::styled("Searching", theme.fg(theme.searching)),
+            CurrentMode::Filtering => Span::styled("Filtering", theme.fg(theme.filtering)),
+            CurrentMode::Palette => Span::styled("Command", theme.fg(theme.searching)),
+            CurrentMode::Deleting => Span::styled("Deleting", theme.fg(theme.error)),
+            CurrentMode::Exiting => Span::styled("Exiting", theme.fg(theme.exiting)),
         }
         .to_owned(),
         // A white divider bar to separate the two sections
-        Span::styled(" | ", Style::default().fg(Color::White)),
+        Span::styled(" | ", theme.fg(theme.text)),
         // The final section of the text, with hints on what the user is editing
         {
             if let Some(editing) = &app.currently_editing {
                 match editing {
                     CurrentlyEditing::Name => {
-                        Span::styled("Editing Recipe Name", Style::default().fg(Color::Green))
+                        Span::styled("Editing Recipe Name", theme.fg(theme.focus))
+                    }
+                    CurrentlyEditing::Description => {
+                        Span::styled("Editing Recipe Description", theme.fg(theme.focus))
+                    }
+                    CurrentlyEditing::Photo => {
+                        Span::styled("Attaching Recipe Photo", theme.fg(theme.focus))
+                    }
+                    CurrentlyEditing::Rename => {
+                        Span::styled("Renaming Recipe", theme.fg(theme.focus))
+                    }
+                    CurrentlyEditing::Duplicate => {
+                        Span::styled("Duplicating Recipe", theme.fg(theme.focus))
                     }
-                    CurrentlyEditing::Description => Span::styled(
-                        "Editing Recipe Description",
-                        Style::default().fg(Color::LightGreen),
-                    ),
-                    _ => Span::styled(
-                        "Editing something else?",
-                        Style::default().fg(Color::LightBlue),
-                    ),
                 }
             } else {
-                Span::styled("Not Editing Anything", Style::default().fg(Color::DarkGray))
+                Span::styled("Not Editing Anything", theme.fg(theme.muted))
             }
         },
     ];
@@ -112,67 +156,257 @@ pub fn entry(frame: &mut Frame, app: &mut App) {
     let mode_footer = Paragraph::new(Line::from(current_navigation_text))
         .block(Block::default().borders(Borders::ALL));
 
+    let footer_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[2]);
+
     let current_keys_hint = {
-        match app.current_mode {
-            CurrentMode::Main => Span::styled(
-                "(q) to quit / (e) to make new pair",
-                Style::default().fg(Color::Red),
-            ),
-            CurrentMode::Editing => Span::styled(
-                "(ESC) to cancel/(Tab) to switch boxes/enter to complete",
-                Style::default().fg(Color::Red),
-            ),
-            CurrentMode::Exiting => Span::styled(
-                "(q) to quit / (e) to make new pair",
-                Style::default().fg(Color::Red),
-            ),
+        let pending = app.keymap.pending();
+        if pending.is_empty() {
+            // Each hint doubles as a button for its action.
+            let inner = footer_chunks[1].inner(Margin::new(1, 1));
+            let mut spans = Vec::new();
+            let mut x = inner.x;
+            let access = app.access();
+            let hints = app.keymap.hints(app.current_mode);
+            for (action, hint) in hints
+                .into_iter()
+                .filter(|(action, _)| access.allows(*action))
+            {
+                if !spans.is_empty() {
+                    spans.push(Span::styled(" / ", theme.fg(theme.hint)));
+                    x += 3;
+                }
+                let width = hint.chars().count() as u16;
+                let area = Rect::new(x, inner.y, width, 1).intersection(inner);
+                app.regions.targets.push((area, Target::Action(action)));
+                spans.push(Span::styled(hint, theme.fg(theme.hint)));
+                x = x.saturating_add(width);
+            }
+            Line::from(spans)
+        } else {
+            let keys: Vec<_> = pending.iter().map(ToString::to_string).collect();
+            Line::styled(format!("{} …", keys.join(" ")), theme.fg(theme.key))
         }
     };
 
     let key_notes_footer =
-        Paragraph::new(Line::from(current_keys_hint)).block(Block::default().borders(Borders::ALL));
-
-    let footer_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[2]);
+        Paragraph::new(current_keys_hint).block(Block::default().borders(Borders::ALL));
 
     frame.render_widget(mode_footer, footer_chunks[0]);
     frame.render_widget(key_notes_footer, footer_chunks[1]);
 
-    if let Some(editing) = app.currently_editing {
-        edit_window(frame, &editing, app);
+    if let CurrentMode::Filtering = app.current_mode {
+        filter_window(frame, app);
+    }
+
+    match app.currently_editing {
+        Some(CurrentlyEditing::Photo) => photo_window(frame, app),
+        Some(CurrentlyEditing::Rename) => name_window(frame, app, "Rename recipe"),
+        Some(CurrentlyEditing::Duplicate) => name_window(frame, app, "Duplicate as variation"),
+        Some(editing) => edit_window(frame, &editing, app),
+        None => {}
+    }
+
+    if let CurrentMode::Deleting = app.current_mode {
+        delete_popup(frame, app);
+    }
+
+    if let CurrentMode::Palette = app.current_mode {
+        palette_window(frame, app);
     }
 
     if let CurrentMode::Exiting = app.current_mode {
-        exit_popup(frame);
+        // It covers the whole screen, nothing below can be clicked.
+        app.regions.targets.clear();
+        exit_popup(frame, &app.theme);
     }
+
+    match app.overlay {
+        Some(Overlay::Help) => help_window(frame, app),
+        Some(Overlay::History) => history_window(frame, app),
+        Some(Overlay::Log) => log_window(frame, app),
+        None => {}
+    }
+
+    // The log already has them all.
+    if app.overlay != Some(Overlay::Log) {
+        toasts(frame, app, chunks[1].y);
+    }
+}
+
+/// A list entry for `hit`, with the characters matching the search highlighted.
+fn highlighted(hit: &Hit, theme: &Theme) -> ListItem<'static> {
+    let spans: Vec<_> = hit
+        .name
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if hit.highlight.contains(&i) {
+                Span::styled(c.to_string(), theme.matched_style())
+            } else {
+                Span::raw(c.to_string())
+            }
+        })
+        .collect();
+    ListItem::from(Line::from(spans))
+}
+
+fn search_bar(frame: &mut Frame<'_>, app: &mut App, area: Rect) {
+    let active = matches!(app.current_mode, CurrentMode::Searching);
+    let theme = &app.theme;
+    let style = theme.fg(if active { theme.searching } else { theme.muted });
+    let mut block = Block::default()
+        .title("/")
+        .borders(Borders::ALL)
+        .style(style);
+    if let Err(err) = app.query() {
+        block = block.title(Line::styled(err.to_string(), theme.fg(theme.error)).right_aligned());
+    }
+    let search_text = &mut app.search_text;
+    search_text.set_block(block);
+    search_text.set_cursor_style(if active {
+        Style::default().reversed()
+    } else {
+        Style::default()
+    });
+    frame.render_widget(&*search_text, area);
+}
+
+fn filter_summary(filter: &Filter, theme: &Theme) -> Paragraph<'static> {
+    let mut parts = Vec::new();
+    if let Some(glass) = filter.glassware {
+        parts.push(format!("glass: {glass}"));
+    }
+    if let Some(spirit) = &filter.base_spirit {
+        parts.push(format!("spirit: {spirit}"));
+    }
+    if let Some(ingredient) = &filter.ingredient {
+        parts.push(format!("has: {ingredient}"));
+    }
+    match (filter.abv_min, filter.abv_max) {
+        (None, None) => {}
+        (Some(min), Some(max)) if min == max => parts.push(format!("abv: {min}%")),
+        (min, max) => parts.push(format!(
+            "abv: {}-{}%",
+            min.map(|v| v.to_string()).unwrap_or_default(),
+            max.map(|v| v.to_string()).unwrap_or_default()
+        )),
+    }
+    Paragraph::new(Line::from(format!("Filters: {}", parts.join(", "))))
+        .style(theme.fg(theme.filtering))
 }
 
-fn recipe_window(app: &App, frame: &mut Frame<'_>, right: Rect) {
+fn filter_window(frame: &mut Frame<'_>, app: &mut App) {
+    let area = centered_rect(60, 60, frame.area());
+    let popup_block = Block::default()
+        .title("Filter recipes")
+        .borders(Borders::NONE)
+        .style(app.theme.popup_style());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup_block, area);
+
+    let fields = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(3); 4])
+        .split(area);
+
+    let active_style = app.theme.field_style();
+    for (field, area) in FilterField::ALL.into_iter().zip(fields.iter()) {
+        let mut block = Block::default().title(field.title()).borders(Borders::ALL);
+        if field == app.filter_field {
+            block = block.style(active_style);
+        }
+        app.regions
+            .targets
+            .push((*area, Target::FilterField(field)));
+        let text = app.filter_text(field);
+        text.set_block(block);
+        frame.render_widget(&*text, *area);
+    }
+}
+
+fn recipe_window(app: &mut App, frame: &mut Frame<'_>, right: Rect) {
     let daiquiri = &app.current_recipe;
-    let glass = glassware::Glass::from(daiquiri.glassware.unwrap_or(Glassware::Highball));
+    let (kind, capacity) = app
+        .repo
+        .glass_for(daiquiri.glass.as_deref(), daiquiri.glassware)
+        .unwrap_or((Glassware::Highball, Glassware::Highball.capacity()));
+    let mut glass = glassware::Glass::from(kind);
+    glass.capacity = Some(capacity);
+    glass.theme = app.theme.clone();
+
+    let recipe = app.repo.enrich(daiquiri.clone());
+    if let Some(recipe) = &recipe {
+        glass = glass.filled(recipe);
+    }
 
     let [left, right] = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Ratio(1, 2); 2])
         .areas(right);
 
+    app.regions.card = left;
     let card = RecipeCard {
+        scroll: app.description_scroll,
         recipe: Some(daiquiri),
+        glass_check: recipe.and_then(|recipe| app.repo.check_glass(&recipe)),
+        // Guests only see the menu.
+        tally: (app.role() >= Role::Bartender).then(|| app.repo.tally(daiquiri)),
+        theme: &app.theme,
     };
 
     frame.render_widget(&card, left);
 
-    frame.render_widget(glass, right);
-    //image::image(frame, right).unwrap();
+    // Products with a photo get a strip of their own under the recipe's.
+    let products: Vec<(String, String)> = daiquiri
+        .ingredients
+        .iter()
+        .filter_map(|(_, name)| {
+            let photo = app.repo.ingredients.get(name)?.photo.clone()?;
+            Some((name.clone(), photo))
+        })
+        .filter(|_| app.photo.visible)
+        .take(PRODUCT_PHOTOS)
+        .collect();
+    let [right, strip] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(if products.is_empty() { 0 } else { 8 }),
+        ])
+        .areas(right);
+    let areas = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Ratio(1, PRODUCT_PHOTOS as u32);
+            products.len()
+        ])
+        .split(strip);
+    for ((name, file), area) in products.iter().zip(areas.iter()) {
+        let block = Block::default().title(name.as_str()).borders(Borders::ALL);
+        let inner = block.inner(*area);
+        frame.render_widget(block, *area);
+        app.photo.render(frame, inner, file);
+    }
+
+    let photo = daiquiri.photo.as_deref().filter(|_| app.photo.visible);
+    let shown = photo.is_some_and(|file| app.photo.render(frame, right, file));
+    if !shown {
+        frame.render_widget(glass, right);
+    }
 }
 
+/// Most product photos shown with a recipe.
+const PRODUCT_PHOTOS: usize = 4;
+
 fn edit_window(frame: &mut Frame<'_>, editing: &CurrentlyEditing, app: &mut App) {
     let popup_block = Block::default()
         .title("Enter a new key-value pair")
         .borders(Borders::NONE)
-        .style(Style::default().bg(Color::DarkGray));
+        .style(app.theme.popup_style());
 
     let area = centered_rect(60, 25, frame.area());
     frame.render_widget(popup_block, area);
@@ -187,14 +421,23 @@ fn edit_window(frame: &mut Frame<'_>, editing: &CurrentlyEditing, app: &mut App)
 
     let mut value_block = Block::default().title("Value").borders(Borders::ALL);
 
-    let active_style = Style::default().bg(Color::LightYellow).fg(Color::Black);
+    let active_style = app.theme.field_style();
 
     match editing {
         CurrentlyEditing::Name => key_block = key_block.style(active_style),
         CurrentlyEditing::Description => value_block = value_block.style(active_style),
-        _ => todo!(),
+        // These have windows of their own.
+        CurrentlyEditing::Photo | CurrentlyEditing::Rename | CurrentlyEditing::Duplicate => {}
     };
 
+    app.regions.targets.extend([
+        (popup_chunks[0], Target::Editing(CurrentlyEditing::Name)),
+        (
+            popup_chunks[1],
+            Target::Editing(CurrentlyEditing::Description),
+        ),
+    ]);
+
     let name_text = &mut app.name_text;
     name_text.set_block(key_block);
 
@@ -205,18 +448,427 @@ fn edit_window(frame: &mut Frame<'_>, editing: &CurrentlyEditing, app: &mut App)
     frame.render_widget(&*desc_text, popup_chunks[1]);
 }
 
-fn exit_popup(frame: &mut Frame<'_>) {
+fn photo_window(frame: &mut Frame<'_>, app: &mut App) {
+    let area = centered_rect(60, 25, frame.area());
+    let popup_block = Block::default()
+        .title(match &app.photo_product {
+            Some(product) => format!("Attach a photo to {product}"),
+            None => "Attach a photo".to_string(),
+        })
+        .borders(Borders::NONE)
+        .style(app.theme.popup_style());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup_block, area);
+
+    let [path_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(3)])
+        .areas(area);
+
+    let path_block = Block::default()
+        .title("Path to image file")
+        .borders(Borders::ALL)
+        .style(app.theme.field_style());
+
+    let photo_text = &mut app.photo_text;
+    photo_text.set_block(path_block);
+    frame.render_widget(&*photo_text, path_area);
+}
+
+/// Asks for the name of the current recipe after a rename or duplicate.
+fn name_window(frame: &mut Frame<'_>, app: &mut App, title: &str) {
+    let area = centered_rect(60, 25, frame.area());
+    let popup_block = Block::default()
+        .title(format!("{title}: {}", app.current_recipe.name))
+        .borders(Borders::NONE)
+        .style(app.theme.popup_style());
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup_block, area);
+
+    let [name_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(3)])
+        .areas(area);
+
+    let name_block = Block::default()
+        .title("New name")
+        .borders(Borders::ALL)
+        .style(app.theme.field_style());
+
+    let new_name_text = &mut app.new_name_text;
+    new_name_text.set_block(name_block);
+    frame.render_widget(&*new_name_text, name_area);
+}
+
+/// The command palette, near the top so the list can grow downwards.
+fn palette_window(frame: &mut Frame<'_>, app: &mut App) {
+    const ROWS: u16 = 10;
+    let theme = &app.theme;
+    let entries = app.palette.entries(&app.keymap, app.access());
+
+    let area = centered_rect(60, 100, frame.area());
+    let area = Rect {
+        y: area.y + area.height / 8,
+        height: (ROWS + 4).min(area.height),
+        ..area
+    };
+    frame.render_widget(Clear, area);
+    let [input_area, list_area] = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .areas(area);
+
+    let title = match app.palette.action {
+        Some(action) => format!(
+            "{}: {}",
+            action.description(),
+            action.argument().unwrap_or_default()
+        ),
+        None => ":".to_string(),
+    };
+    let input_block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .style(theme.popup_style());
+    let palette_text = &mut app.palette.text;
+    palette_text.set_block(input_block);
+    frame.render_widget(&*palette_text, input_area);
+
+    let list_block = Block::default()
+        .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
+        .style(theme.popup_style());
+    let inner = list_block.inner(list_area);
+    let width = usize::from(inner.width);
+    let items: Vec<ListItem> = entries
+        .iter()
+        .map(|entry| {
+            let mut spans: Vec<Span> = entry
+                .label
+                .chars()
+                .enumerate()
+                .map(|(i, c)| {
+                    if entry.highlight.contains(&i) {
+                        Span::styled(c.to_string(), theme.matched_style())
+                    } else {
+                        Span::raw(c.to_string())
+                    }
+                })
+                .collect();
+            // Keys go to the right edge.
+            let used = entry.label.chars().count() + entry.keys.chars().count();
+            spans.push(Span::raw(" ".repeat(width.saturating_sub(used + 1).max(1))));
+            spans.push(Span::styled(entry.keys.clone(), theme.fg(theme.key)));
+            ListItem::from(Line::from(spans))
+        })
+        .collect();
+    let empty = items.is_empty();
+    let list = List::new(items)
+        .block(list_block)
+        .highlight_style(theme.selection_style());
+
+    let selected = app.palette.list_state.selected().unwrap_or(0);
+    app.palette
+        .list_state
+        .select(Some(selected.min(entries.len().saturating_sub(1))));
+    frame.render_stateful_widget(list, list_area, &mut app.palette.list_state);
+    if empty {
+        frame.render_widget(
+            Paragraph::new(Line::styled("No matches", theme.fg(theme.muted))),
+            inner,
+        );
+    }
+
+    let offset = app.palette.list_state.offset();
+    let visible = entries
+        .len()
+        .saturating_sub(offset)
+        .min(usize::from(inner.height));
+    for row in 0..visible {
+        let area = Rect {
+            y: inner.y + row as u16,
+            height: 1,
+            ..inner
+        };
+        app.regions
+            .targets
+            .push((area, Target::PaletteEntry(offset + row)));
+    }
+}
+
+fn delete_popup(frame: &mut Frame<'_>, app: &mut App) {
+    let theme = &app.theme;
+    let block = Block::default()
+        .title("Delete")
+        .borders(Borders::ALL)
+        .style(theme.popup_style());
+    let text = Text::from(vec![
+        Line::styled(
+            format!("Delete {}? (y/n)", app.current_recipe.name),
+            theme.fg(theme.error),
+        ),
+        Line::styled("It can be undone.", theme.fg(theme.muted)),
+    ]);
+
+    let area = overlay_area(frame.area(), text.lines.len());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(text).block(block), area);
+}
+
+/// Every action of the current mode with the keys bound to it, straight
+/// from the keymap.
+fn help_window(frame: &mut Frame<'_>, app: &App) {
+    let mode = match app.current_mode {
+        CurrentMode::Main => "viewing",
+        CurrentMode::Editing => "editing",
+        CurrentMode::Searching => "searching",
+        CurrentMode::Filtering => "filtering",
+        CurrentMode::Palette => "command palette",
+        CurrentMode::Deleting => "deleting",
+        CurrentMode::Exiting => "exiting",
+    };
+    let screen = match app.current_screen {
+        CurrentScreen::Recipes => "Recipes",
+        CurrentScreen::Ingredients => "Ingredients",
+    };
+
+    let access = app.access();
+    let mut actions = app.keymap.actions(app.current_mode);
+    actions.retain(|(action, _)| access.allows(*action));
+    let keys: Vec<_> = actions
+        .iter()
+        .map(|(_, chords)| {
+            let chords: Vec<_> = chords.iter().map(ToString::to_string).collect();
+            chords.join(", ")
+        })
+        .collect();
+    let width = keys.iter().map(|k| k.chars().count()).max().unwrap_or(0);
+
+    let mut lines: Vec<Line> = actions
+        .iter()
+        .zip(&keys)
+        .map(|((action, _), keys)| {
+            Line::from(vec![
+                Span::styled(format!("{keys:>width$}  "), app.theme.fg(app.theme.key)),
+                Span::raw(action.description()),
+            ])
+        })
+        .collect();
+    if !matches!(app.current_mode, CurrentMode::Main | CurrentMode::Exiting) {
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            "Other keys go to the text field.",
+            app.theme.fg(app.theme.muted),
+        ));
+    }
+
+    let block = Block::default()
+        .title(format!("Keys: {screen}, {mode}"))
+        .title_bottom(Line::from("any key to close").right_aligned())
+        .borders(Borders::ALL)
+        .style(app.theme.popup_style());
+
+    let area = overlay_area(frame.area(), lines.len());
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Every step that can be undone, and then those that can be redone.
+fn history_window(frame: &mut Frame<'_>, app: &App) {
+    let theme = &app.theme;
+    let step_lines = |step: &Step, style: Style| {
+        let mut details: Vec<String> = Vec::new();
+        // The recipe being edited and its saved copy often change alike.
+        for detail in step.changes.iter().flat_map(Change::details) {
+            if !details.contains(&detail) {
+                details.push(detail);
+            }
+        }
+        let mut lines = vec![Line::styled(step.description.clone(), style)];
+        lines.extend(
+            details
+                .into_iter()
+                .map(|detail| Line::styled(format!("  {detail}"), theme.fg(theme.muted))),
+        );
+        lines
+    };
+
+    // What the others working on the collection did, as they can't be
+    // undone from here.
+    let collection = app.current_collection();
+    let others: Vec<Line> = collection
+        .repo
+        .journal()
+        .into_iter()
+        .filter(|edit| edit.author != app.user)
+        .map(|edit| {
+            // The journal is kept across restarts, so it goes back days.
+            let today = edit.time.date_naive() == Local::now().date_naive();
+            let time = edit
+                .time
+                .format(if today { "%H:%M" } else { "%b %d %H:%M" });
+            Line::from(vec![
+                Span::styled(format!("{time} {} ", edit.author), theme.fg(theme.muted)),
+                Span::styled(edit.description, theme.fg(theme.text)),
+            ])
+        })
+        .collect();
+    let mut lines = Vec::new();
+    if !others.is_empty() {
+        lines.push(Line::styled(
+            format!("changed by others in {}:", collection.name),
+            theme.fg(theme.key),
+        ));
+        lines.extend(others);
+        lines.push(Line::styled("yours:", theme.fg(theme.key)));
+    }
+
+    let done = app.history.done();
+    lines.extend(
+        done.iter()
+            .flat_map(|step| step_lines(step, theme.fg(theme.text))),
+    );
+    if done.is_empty() {
+        lines.push(Line::styled("Nothing to undo", theme.fg(theme.muted)));
+    }
+    if !app.history.undone().is_empty() {
+        lines.push(Line::styled("undone, can be redone:", theme.fg(theme.key)));
+        lines.extend(
+            app.history
+                .undone()
+                .iter()
+                .rev()
+                .flat_map(|step| step_lines(step, theme.fg(theme.muted).italic())),
+        );
+    }
+
+    let block = Block::default()
+        .title("History")
+        .title_bottom(Line::from("any key to close").right_aligned())
+        .borders(Borders::ALL)
+        .style(theme.popup_style());
+
+    let area = overlay_area(frame.area(), lines.len());
+    // Keep the latest steps in view.
+    let hidden = lines
+        .len()
+        .saturating_sub(usize::from(area.height.saturating_sub(2)));
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((hidden as u16, 0)),
+        area,
+    );
+}
+
+/// Everything logged so far, the latest at the bottom.
+fn log_window(frame: &mut Frame<'_>, app: &mut App) {
+    let theme = &app.theme;
+    let records = app.log.records();
+    let mut lines: Vec<Line> = records
+        .iter()
+        .map(|record| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{} {:>5} ", record.time.format("%H:%M:%S"), record.level),
+                    theme.fg(level_color(theme, record.level)),
+                ),
+                Span::styled(record.message.clone(), theme.fg(theme.text)),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::styled("Nothing logged yet", theme.fg(theme.muted)));
+    }
+
+    let area = centered_rect(80, 80, frame.area());
+    let height = usize::from(area.height.saturating_sub(2));
+    let bottom = lines.len().saturating_sub(height);
+    app.log_scroll = app.log_scroll.min(bottom);
+    let mut block = Block::default()
+        .title("Log")
+        .title_bottom(Line::from("up/down to scroll, any other key to close").right_aligned())
+        .borders(Borders::ALL)
+        .style(theme.popup_style());
+    if app.log_scroll > 0 {
+        block = block.title(Line::from(format!("{} newer", app.log_scroll)).right_aligned());
+    }
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .scroll(((bottom - app.log_scroll) as u16, 0)),
+        area,
+    );
+}
+
+/// Toasts stacked in the top right corner from `top` down, over everything
+/// else.
+fn toasts(frame: &mut Frame<'_>, app: &App, top: u16) {
+    let theme = &app.theme;
+    let area = frame.area();
+    let mut y = top;
+    for toast in app.log.toasts() {
+        let max = usize::from(area.width / 2).saturating_sub(2).max(1);
+        let lines: Vec<Line> = textwrap::wrap(&toast.message, max)
+            .into_iter()
+            .map(|line| Line::styled(line.into_owned(), theme.fg(theme.text)))
+            .collect();
+        let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 2;
+        let height = lines.len() as u16 + 2;
+        let toast_area = Rect {
+            x: area.right().saturating_sub(width + 1),
+            y,
+            width,
+            height,
+        }
+        .intersection(area);
+        let color = match toast.level {
+            tracing::Level::INFO => theme.success,
+            level => level_color(theme, level),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.fg(color))
+            .style(theme.popup_style());
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(Paragraph::new(lines).block(block), toast_area);
+        y += height;
+    }
+}
+
+fn level_color(theme: &Theme, level: tracing::Level) -> Color {
+    match level {
+        tracing::Level::ERROR => theme.error,
+        tracing::Level::WARN => theme.warning,
+        tracing::Level::INFO => theme.text,
+        _ => theme.muted,
+    }
+}
+
+/// A centered area tall enough for `lines` lines and a border.
+fn overlay_area(area: Rect, lines: usize) -> Rect {
+    let height = lines as u16 + 2;
+    let area = centered_rect(60, 100, area);
+    Rect {
+        y: area.y + area.height.saturating_sub(height) / 2,
+        height: height.min(area.height),
+        ..area
+    }
+}
+
+fn exit_popup(frame: &mut Frame<'_>, theme: &Theme) {
     frame.render_widget(Clear, frame.area());
     //this clears the entire screen and anything already drawn
     let popup_block = Block::default()
         .title("Y/N")
         .borders(Borders::NONE)
-        .style(Style::default().bg(Color::DarkGray));
+        .style(theme.popup_style());
 
-    let exit_text = Text::styled(
-        "Would you like to output the buffer as json? (y/n)",
-        Style::default().fg(Color::Red),
-    );
+    let exit_text = Text::styled("Quit? (y/n)", theme.fg(theme.error));
     // the `trim: false` will stop the text from being cut off when over the edge of the block
     let exit_paragraph = Paragraph::new(exit_text)
         .block(popup_block)
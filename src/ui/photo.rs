@@ -0,0 +1,122 @@
+use std::{fmt, path::PathBuf};
+
+use ratatui::prelude::*;
+use ratatui_image::{
+    picker::{Picker, ProtocolType},
+    protocol::StatefulProtocol,
+    StatefulImage,
+};
+
+use crate::sys::photo;
+
+/// Font size assumed when the terminal can't tell us its own.
+const FALLBACK_FONT_SIZE: (u16, u16) = (8, 16);
+
+/// Photos kept encoded between frames, enough for a recipe and the products
+/// shown with it.
+const LOADED: usize = 8;
+
+/// Picks the image protocol and keeps the encoded photo around between frames.
+pub struct PhotoState {
+    pub picker: Picker,
+    pub visible: bool,
+    /// The most recently drawn last.
+    loaded: Vec<(PathBuf, StatefulProtocol)>,
+}
+
+impl PhotoState {
+    /// Halfblocks only, which works in any terminal.
+    pub fn new() -> Self {
+        let mut picker = Picker::from_fontsize(FALLBACK_FONT_SIZE);
+        picker.set_protocol_type(ProtocolType::Halfblocks);
+        PhotoState {
+            picker,
+            visible: false,
+            loaded: Vec::new(),
+        }
+    }
+
+    /// Ask the terminal for its font size and the best protocol it supports,
+    /// be it sixel, kitty or iTerm2, falling back to halfblocks.
+    ///
+    /// This talks to the terminal over stdin/stdout, so it has to be called
+    /// after entering the alternate screen but before reading any events.
+    pub fn probe() -> Self {
+        let picker = Picker::from_query_stdio()
+            .unwrap_or_else(|_| Picker::from_fontsize(FALLBACK_FONT_SIZE));
+        PhotoState {
+            picker,
+            ..Self::new()
+        }
+    }
+
+    /// Guess the best protocol from the `TERM` of a terminal we can't ask,
+    /// like one on the other end of an ssh connection. `font_size` is in
+    /// pixels, when the terminal told.
+    pub fn for_terminal(term: &str, font_size: Option<(u16, u16)>) -> Self {
+        let mut picker = Picker::from_fontsize(font_size.unwrap_or(FALLBACK_FONT_SIZE));
+        picker.set_protocol_type(match term {
+            "xterm-kitty" | "xterm-ghostty" => ProtocolType::Kitty,
+            "wezterm" => ProtocolType::Iterm2,
+            "foot" | "foot-extra" | "mlterm" => ProtocolType::Sixel,
+            term if term.contains("sixel") => ProtocolType::Sixel,
+            _ => ProtocolType::Halfblocks,
+        });
+        PhotoState {
+            picker,
+            ..Self::new()
+        }
+    }
+
+    /// Render the stored photo `file` into `area`.
+    ///
+    /// Returns `false` if the photo couldn't be loaded.
+    pub fn render(&mut self, frame: &mut Frame<'_>, area: Rect, file: &str) -> bool {
+        let Some(path) = photo::resolve(file) else {
+            return false;
+        };
+
+        let loaded = match self.loaded.iter().position(|(loaded, _)| *loaded == path) {
+            Some(i) => self.loaded.remove(i),
+            None => {
+                let image = match image::ImageReader::open(&path).map(|reader| reader.decode()) {
+                    Ok(Ok(image)) => image,
+                    _ => {
+                        tracing::warn!("Could not load photo {path:?}");
+                        return false;
+                    }
+                };
+                (path, self.picker.new_resize_protocol(image))
+            }
+        };
+        if self.loaded.len() >= LOADED {
+            self.loaded.remove(0);
+        }
+        self.loaded.push(loaded);
+
+        let Some((_, protocol)) = self.loaded.last_mut() else {
+            return false;
+        };
+        frame.render_stateful_widget(StatefulImage::new(None), area, protocol);
+        true
+    }
+}
+
+impl Default for PhotoState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for PhotoState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhotoState")
+            .field("picker", &self.picker)
+            .field("visible", &self.visible)
+            .field(
+                "loaded",
+                &self.loaded.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
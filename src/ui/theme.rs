@@ -0,0 +1,445 @@
+//! Colours used across the UI, and what the terminal can actually show.
+//!
+//! The theme is picked in `theme.toml` in the config directory, either one
+//! of the built-in palettes or a file in the `themes` directory next to it,
+//! and single colours can be overridden on top:
+//!
+//! ```toml
+//! theme = "light"
+//! ascii = false
+//!
+//! [colors]
+//! title = "#2e7d32"
+//! selection = "yellow"
+//! ```
+
+use std::{collections::BTreeMap, fs, path::PathBuf, str::FromStr};
+
+use eyre::{eyre, Result, WrapErr};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use serde::{Deserialize, Serialize};
+
+use crate::sys::{is_file_name, project_dirs};
+
+/// How many colours the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    /// `NO_COLOR` is set, or the terminal is dumb.
+    None,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+/// The 16 ANSI colours, with the RGB values xterm uses for them.
+const ANSI: [(Color, [u8; 3]); 16] = [
+    (Color::Black, [0, 0, 0]),
+    (Color::Red, [205, 0, 0]),
+    (Color::Green, [0, 205, 0]),
+    (Color::Yellow, [205, 205, 0]),
+    (Color::Blue, [0, 0, 238]),
+    (Color::Magenta, [205, 0, 205]),
+    (Color::Cyan, [0, 205, 205]),
+    (Color::Gray, [229, 229, 229]),
+    (Color::DarkGray, [127, 127, 127]),
+    (Color::LightRed, [255, 0, 0]),
+    (Color::LightGreen, [0, 255, 0]),
+    (Color::LightYellow, [255, 255, 0]),
+    (Color::LightBlue, [92, 92, 255]),
+    (Color::LightMagenta, [255, 0, 255]),
+    (Color::LightCyan, [0, 255, 255]),
+    (Color::White, [255, 255, 255]),
+];
+
+impl ColorSupport {
+    /// Look at `NO_COLOR`, `COLORTERM` and `TERM`, as found by `var`.
+    pub fn detect(var: impl Fn(&str) -> Option<String>) -> ColorSupport {
+        if var("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorSupport::None;
+        }
+        if var("COLORTERM").is_some_and(|value| value == "truecolor" || value == "24bit") {
+            return ColorSupport::TrueColor;
+        }
+        match var("TERM").as_deref() {
+            Some("dumb") => ColorSupport::None,
+            Some(term) if term.contains("256color") => ColorSupport::Ansi256,
+            _ => ColorSupport::Ansi16,
+        }
+    }
+
+    /// The closest colour the terminal can show.
+    pub fn adapt(&self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::None, _) => Color::Reset,
+            (ColorSupport::TrueColor, _) => color,
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => {
+                let level = |v: u8| (u16::from(v) * 5 + 127) / 255;
+                Color::Indexed((16 + 36 * level(r) + 6 * level(g) + level(b)) as u8)
+            }
+            (ColorSupport::Ansi256, _) => color,
+            (ColorSupport::Ansi16, Color::Rgb(r, g, b)) => nearest_ansi([r, g, b]),
+            (ColorSupport::Ansi16, Color::Indexed(i)) => match ANSI.get(usize::from(i)) {
+                Some((ansi, _)) => *ansi,
+                None => nearest_ansi(indexed_rgb(i)),
+            },
+            (ColorSupport::Ansi16, _) => color,
+        }
+    }
+}
+
+fn nearest_ansi([r, g, b]: [u8; 3]) -> Color {
+    let distance = |[r2, g2, b2]: [u8; 3]| {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    ANSI.iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+/// RGB of one of the 240 extended colours of a 256 colour terminal.
+fn indexed_rgb(i: u8) -> [u8; 3] {
+    if i >= 232 {
+        let v = 8 + 10 * (i - 232);
+        return [v, v, v];
+    }
+    let i = i - 16;
+    let level = |v: u8| if v == 0 { 0 } else { 55 + 40 * v };
+    [level(i / 36), level(i / 6 % 6), level(i % 6)]
+}
+
+/// Whether the terminal can be trusted with Braille and box drawing
+/// characters, going by the locale and `TERM`, as found by `var`.
+pub fn detect_unicode(var: impl Fn(&str) -> Option<String>) -> bool {
+    if matches!(var("TERM").as_deref(), Some("linux" | "dumb")) {
+        return false;
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(&var)
+        .find(|value| !value.is_empty());
+    locale.is_none_or(|locale| {
+        let locale = locale.to_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub color_support: ColorSupport,
+    /// Draw glasses with plain ASCII instead of Braille.
+    pub ascii: bool,
+
+    pub title: Color,
+    pub text: Color,
+    pub muted: Color,
+    pub recipe_name: Color,
+    pub viewing: Color,
+    pub editing: Color,
+    pub searching: Color,
+    pub filtering: Color,
+    pub exiting: Color,
+    /// What is being edited, in the footer.
+    pub focus: Color,
+    pub hint: Color,
+    pub key: Color,
+    pub selection: Color,
+    /// Characters matching the search.
+    pub matched: Color,
+    pub error: Color,
+    pub warning: Color,
+    /// Something went as asked.
+    pub success: Color,
+    pub popup: Color,
+    pub field: Color,
+    pub field_text: Color,
+    pub glass: Color,
+    /// Liquid without any coloured ingredients.
+    pub clear_liquid: Color,
+    pub ice: Color,
+}
+
+impl Theme {
+    pub const PRESETS: [&'static str; 2] = ["dark", "light"];
+
+    pub fn dark() -> Theme {
+        Theme {
+            name: "dark".to_string(),
+            color_support: ColorSupport::TrueColor,
+            ascii: false,
+            title: Color::Green,
+            text: Color::White,
+            muted: Color::DarkGray,
+            recipe_name: Color::Blue,
+            viewing: Color::Green,
+            editing: Color::Yellow,
+            searching: Color::Cyan,
+            filtering: Color::Magenta,
+            exiting: Color::LightRed,
+            focus: Color::LightGreen,
+            hint: Color::Red,
+            key: Color::Yellow,
+            selection: Color::Yellow,
+            matched: Color::Cyan,
+            error: Color::Red,
+            warning: Color::Yellow,
+            success: Color::Green,
+            popup: Color::DarkGray,
+            field: Color::LightYellow,
+            field_text: Color::Black,
+            glass: Color::White,
+            clear_liquid: Color::Rgb(200, 220, 230),
+            ice: Color::Rgb(235, 250, 255),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            name: "light".to_string(),
+            title: Color::Rgb(46, 125, 50),
+            text: Color::Black,
+            muted: Color::Gray,
+            recipe_name: Color::Rgb(21, 101, 192),
+            viewing: Color::Rgb(46, 125, 50),
+            editing: Color::Rgb(191, 112, 0),
+            searching: Color::Rgb(0, 131, 143),
+            filtering: Color::Rgb(142, 36, 170),
+            exiting: Color::Rgb(198, 40, 40),
+            focus: Color::Rgb(56, 142, 60),
+            hint: Color::Rgb(183, 28, 28),
+            key: Color::Rgb(191, 112, 0),
+            selection: Color::Rgb(191, 112, 0),
+            matched: Color::Rgb(0, 131, 143),
+            error: Color::Rgb(198, 40, 40),
+            warning: Color::Rgb(191, 112, 0),
+            success: Color::Rgb(46, 125, 50),
+            popup: Color::Rgb(224, 224, 224),
+            field: Color::Rgb(255, 245, 157),
+            field_text: Color::Black,
+            glass: Color::Black,
+            clear_liquid: Color::Rgb(144, 164, 174),
+            ice: Color::Rgb(179, 229, 252),
+            ..Theme::dark()
+        }
+    }
+
+    /// A built-in palette, or a theme file from the `themes` directory.
+    pub fn named(name: &str) -> Result<Theme> {
+        match name {
+            "dark" => return Ok(Theme::dark()),
+            "light" => return Ok(Theme::light()),
+            _ => {}
+        }
+
+        // Guests pick themes too, so only names of files in the directory.
+        let path = Self::dir()
+            .filter(|_| is_file_name(name))
+            .map(|dir| dir.join(format!("{name}.toml")))
+            .filter(|path| path.exists())
+            .ok_or_else(|| {
+                eyre!(
+                    "Unknown theme {name:?}, try {} or a file in the themes directory",
+                    Self::PRESETS.join(", ")
+                )
+            })?;
+        let file = ThemeFile::read(&path)?;
+        // Theme files build on a built-in palette, not on each other.
+        let base = match file.theme.as_deref() {
+            Some("light") => Theme::light(),
+            _ => Theme::dark(),
+        };
+        let mut theme = base.with(&file)?;
+        theme.name = name.to_string();
+        Ok(theme)
+    }
+
+    /// Where user theme files live.
+    pub fn dir() -> Option<PathBuf> {
+        Some(project_dirs()?.config_dir().join("themes"))
+    }
+
+    /// The built-in palettes, then the theme files.
+    pub fn available() -> Vec<String> {
+        let mut names: Vec<String> = Self::PRESETS.map(str::to_string).into();
+        let files = Self::dir().and_then(|dir| fs::read_dir(dir).ok());
+        let mut files: Vec<String> = files
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+                is_toml.then(|| path.file_stem()?.to_str().map(str::to_string))?
+            })
+            .collect();
+        files.sort();
+        names.extend(files);
+        names
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Some(project_dirs()?.config_dir().join("theme.toml"))
+    }
+
+    /// Load the chosen theme and fit it to the terminal we are running in.
+    pub fn load() -> Result<Theme> {
+        Self::load_for(|name| std::env::var(name).ok())
+    }
+
+    /// Load the chosen theme and fit it to the terminal described by `var`,
+    /// like the one of someone logged in over ssh.
+    pub fn load_for(var: impl Fn(&str) -> Option<String> + Copy) -> Result<Theme> {
+        let file = match Self::path().filter(|path| path.exists()) {
+            Some(path) => ThemeFile::read(&path)?,
+            None => ThemeFile::default(),
+        };
+        let theme = Theme::named(file.theme.as_deref().unwrap_or("dark"))?.with(&file)?;
+
+        let mut theme = theme.fit(ColorSupport::detect(var), !detect_unicode(var));
+        if let Some(ascii) = file.ascii {
+            theme.ascii = ascii;
+        }
+        Ok(theme)
+    }
+
+    /// Override the colours set in `file`.
+    fn with(mut self, file: &ThemeFile) -> Result<Theme> {
+        for (slot, color) in &file.colors {
+            let parsed =
+                Color::from_str(color).map_err(|_| eyre!("Unknown color {color:?} for {slot}"))?;
+            *self
+                .slot(slot)
+                .ok_or_else(|| eyre!("Unknown theme color {slot:?}"))? = parsed;
+        }
+        Ok(self)
+    }
+
+    fn slot(&mut self, name: &str) -> Option<&mut Color> {
+        let slot = match name {
+            "title" => &mut self.title,
+            "text" => &mut self.text,
+            "muted" => &mut self.muted,
+            "recipe_name" => &mut self.recipe_name,
+            "viewing" => &mut self.viewing,
+            "editing" => &mut self.editing,
+            "searching" => &mut self.searching,
+            "filtering" => &mut self.filtering,
+            "exiting" => &mut self.exiting,
+            "focus" => &mut self.focus,
+            "hint" => &mut self.hint,
+            "key" => &mut self.key,
+            "selection" => &mut self.selection,
+            "matched" => &mut self.matched,
+            "error" => &mut self.error,
+            "warning" => &mut self.warning,
+            "success" => &mut self.success,
+            "popup" => &mut self.popup,
+            "field" => &mut self.field,
+            "field_text" => &mut self.field_text,
+            "glass" => &mut self.glass,
+            "clear_liquid" => &mut self.clear_liquid,
+            "ice" => &mut self.ice,
+            _ => return None,
+        };
+        Some(slot)
+    }
+
+    /// Bring every colour down to what the terminal supports.
+    pub fn fit(mut self, support: ColorSupport, ascii: bool) -> Theme {
+        self.color_support = support;
+        self.ascii = ascii;
+        for slot in [
+            &mut self.title,
+            &mut self.text,
+            &mut self.muted,
+            &mut self.recipe_name,
+            &mut self.viewing,
+            &mut self.editing,
+            &mut self.searching,
+            &mut self.filtering,
+            &mut self.exiting,
+            &mut self.focus,
+            &mut self.hint,
+            &mut self.key,
+            &mut self.selection,
+            &mut self.matched,
+            &mut self.error,
+            &mut self.warning,
+            &mut self.success,
+            &mut self.popup,
+            &mut self.field,
+            &mut self.field_text,
+            &mut self.glass,
+            &mut self.clear_liquid,
+            &mut self.ice,
+        ] {
+            *slot = support.adapt(*slot);
+        }
+        self
+    }
+
+    /// A colour from the recipe data, like the colour of a liquid.
+    pub fn adapt(&self, color: Color) -> Color {
+        self.color_support.adapt(color)
+    }
+
+    pub fn fg(&self, color: Color) -> Style {
+        Style::default().fg(color)
+    }
+
+    /// The selected entry of a list.
+    pub fn selection_style(&self) -> Style {
+        self.emphasis(self.fg(self.selection), Modifier::REVERSED)
+    }
+
+    /// Characters matching the search.
+    pub fn matched_style(&self) -> Style {
+        self.emphasis(self.fg(self.matched).bold(), Modifier::UNDERLINED)
+    }
+
+    /// The text field that has focus.
+    pub fn field_style(&self) -> Style {
+        self.emphasis(
+            Style::default().bg(self.field).fg(self.field_text),
+            Modifier::REVERSED,
+        )
+    }
+
+    pub fn popup_style(&self) -> Style {
+        Style::default().bg(self.popup)
+    }
+
+    /// Without colours, fall back on `modifier` to make `style` stand out.
+    fn emphasis(&self, style: Style, modifier: Modifier) -> Style {
+        if self.color_support == ColorSupport::None {
+            style.add_modifier(modifier)
+        } else {
+            style
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+/// `theme.toml`, or a file in the `themes` directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThemeFile {
+    /// The theme to start from.
+    pub theme: Option<String>,
+    /// Force ASCII drawings on or off.
+    pub ascii: Option<bool>,
+    /// Colour slot to colour, like `title = "#2e7d32"`.
+    #[serde(default)]
+    pub colors: BTreeMap<String, String>,
+}
+
+impl ThemeFile {
+    fn read(path: &std::path::Path) -> Result<ThemeFile> {
+        toml::from_str(&fs::read_to_string(path)?)
+            .wrap_err_with(|| format!("Could not read {path:?}"))
+    }
+}
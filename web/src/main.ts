@@ -2,7 +2,24 @@ import { Terminal } from "@xterm/xterm";
 import { FitAddon } from "@xterm/addon-fit";
 import { WebLinksAddon } from '@xterm/addon-web-links';
 import { CanvasAddon } from "@xterm/addon-canvas";
-import { AttachAddon } from "@xterm/addon-attach";
+
+
+// See server/src/protocol.rs, binary messages from the server are terminal
+// output and everything else is JSON.
+const PROTOCOL_VERSIONS = [1];
+
+type ClientMessage =
+  | { type: "hello"; versions: number[] }
+  | { type: "input"; data: string }
+  | { type: "resize"; cols: number; rows: number }
+  | { type: "ping"; id: number }
+  | { type: "clipboard"; text: string };
+
+type ServerMessage =
+  | { type: "hello"; version: number }
+  | { type: "pong"; id: number }
+  | { type: "title"; title: string }
+  | { type: "notice"; level: "warning" | "error"; text: string };
 
 
 const term = new Terminal({
@@ -10,13 +27,16 @@ const term = new Terminal({
 });
 const fitAddon = new FitAddon();
 const socket = new WebSocket("ws://localhost:1111");
+socket.binaryType = "arraybuffer";
+const notice = document.getElementById("notice")!;
 
 
 term.loadAddon(fitAddon);
 term.loadAddon(new WebLinksAddon());
 term.loadAddon(new CanvasAddon());
 
-term.open(document.getElementById("terminal")!);
+const element = document.getElementById("terminal")!;
+term.open(element);
 
 fitAddon.fit();
 
@@ -27,30 +47,57 @@ window.addEventListener('resize', () => {
 });
 
 
-socket.onopen = () => {
-    const packet = new Uint8Array(3);
-
-    packet[0] = 0x04;
-    packet[1] = Math.min(term.cols, 255);
-    packet[2] = Math.min(term.rows, 255);
+function send(message: ClientMessage) {
+  if (socket.readyState === WebSocket.OPEN) {
+    socket.send(JSON.stringify(message));
+  }
+}
+
+function receive(message: ServerMessage) {
+  switch (message.type) {
+    case "hello":
+      console.log(`speaking protocol version ${message.version}`);
+      send({ type: "resize", cols: term.cols, rows: term.rows });
+      term.focus();
+      break;
+    case "pong":
+      break;
+    case "title":
+      document.title = message.title;
+      break;
+    case "notice":
+      notice.textContent = message.text;
+      notice.className = message.level;
+      break;
+  }
+}
 
-    socket.send(packet);
 
-    term.loadAddon(
-        new AttachAddon(socket, {
-            bidirectional: true,
-        }),
-    );
-
-    term.onResize((event) => {
-        const packet = new Uint8Array(3);
-
-        packet[0] = 0x04;
-        packet[1] = Math.min(event.cols, 255);
-        packet[2] = Math.min(event.rows, 255);
-
-        socket.send(packet);
-    });
+socket.onopen = () => {
+    send({ type: "hello", versions: PROTOCOL_VERSIONS });
+
+    term.onData((data) => send({ type: "input", data }));
+    term.onResize(({ cols, rows }) => send({ type: "resize", cols, rows }));
+
+    // Pastes go as they are, rather than typed in key by key.
+    element.addEventListener("paste", (event) => {
+        const text = event.clipboardData?.getData("text/plain");
+        if (text) {
+            send({ type: "clipboard", text });
+        }
+        event.preventDefault();
+        event.stopPropagation();
+    }, true);
+
+    // Keeps proxies from dropping a quiet connection.
+    let ping = 0;
+    setInterval(() => send({ type: "ping", id: ++ping }), 30_000);
+};
 
-    term.focus();
+socket.onmessage = (event) => {
+    if (event.data instanceof ArrayBuffer) {
+        term.write(new Uint8Array(event.data));
+    } else {
+        receive(JSON.parse(event.data));
+    }
 };